@@ -0,0 +1,69 @@
+; behaviour of the demons by color, minis share the profile of their parent
+; the kamikaze, shielded and sniper archetypes have a profile of their own
+;
+; (profile color setting...)
+;   (speed v)                  how fast it jitters around while shooting, px/s
+;   (shoot-time v)             seconds between shooting
+;   (bursts n)                 how often the pattern runs back to back when shooting
+;   (pattern name [weight])    bullet pattern from assets/patterns/demons.pattern, picked by weight on spawn
+;   (path name [weight])       path from assets/waves/demons.wave, picked by weight on spawn, none holds still
+;   (children chance min max)  chance to split into min..max minis on death
+;   (dodge speed range)        sidesteps player bullets closer than range
+;   (armor arc hits kind...)   soaks up hits coming in within arc degrees of where it faces, straight down
+;                              unless it is a shielded demon, and bullets of the listed kinds from anywhere,
+;                              breaks after hits
+;   (behaviour name)           tree from assets/behaviours/demons.behaviour, normal by default
+;   (mini-behaviour name)      tree its minis run, mini by default
+
+
+(profile purple
+  (speed 40)
+  (shoot-time 2.5)
+  (bursts 1)
+  (pattern burst 3)
+  (pattern spiral 1)
+  (path hover 2)
+  (path figure-eight 1)
+  (children 0.8 2 3)
+  (armor 90 2 missile))
+
+(profile red
+  (speed 60)
+  (shoot-time 1.4)
+  (bursts 2)
+  (pattern aimed 3)
+  (pattern sniper 1)
+  (path zigzag 2)
+  (path swoop 1)
+  (children 0.25 1 1))
+
+(profile green
+  (speed 50)
+  (shoot-time 2)
+  (bursts 1)
+  (pattern burst 2)
+  (pattern curve 1)
+  (path hover 1)
+  (path drift-down 1)
+  (children 0.4 1 2)
+  (dodge 70 40)
+  (behaviour skittish))
+
+(profile kamikaze
+  (speed 50)
+  (path drift-down 1)
+  (behaviour kamikaze))
+
+; turns its shield towards the player, hit it from the side or wear the shield down
+(profile shielded
+  (speed 30)
+  (shoot-time 2.2)
+  (pattern aimed 1)
+  (path hover 1)
+  (armor 90 5))
+
+(profile sniper
+  (speed 40)
+  (shoot-time 2.5)
+  (path hover 1)
+  (behaviour sniper))
@@ -0,0 +1,116 @@
+// systems::broadphase and the hash queries against checking every pair of bullets, at rising bullet counts
+// cargo bench --bench broadphase
+// the game modules get pulled in whole and are linted with the game, only the collision side is used here
+#![allow(unused, clippy::all)]
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use macroquad::prelude::*;
+
+// the game is a binary, so the modules broadphase needs come in the way main.rs declares them
+#[path = "../src/behaviour.rs"] mod behaviour;
+#[path = "../src/boss.rs"] mod boss;
+#[path = "../src/bullet.rs"] mod bullet;
+#[path = "../src/bullet_pattern.rs"] mod bullet_pattern;
+#[path = "../src/bunker.rs"] mod bunker;
+#[path = "../src/constants.rs"] mod constants;
+#[path = "../src/ecs.rs"] mod ecs;
+#[path = "../src/enermy.rs"] mod enermy;
+#[path = "../src/enermy_profile.rs"] mod enermy_profile;
+#[path = "../src/flocking.rs"] mod flocking;
+#[path = "../src/formation.rs"] mod formation;
+#[path = "../src/hazard.rs"] mod hazard;
+#[path = "../src/player.rs"] mod player;
+#[path = "../src/resources.rs"] mod resources;
+#[path = "../src/sexpr.rs"] mod sexpr;
+#[path = "../src/spatial_hash.rs"] mod spatial_hash;
+#[path = "../src/spline.rs"] mod spline;
+#[path = "../src/sprite_mask.rs"] mod sprite_mask;
+#[path = "../src/sweep.rs"] mod sweep;
+#[path = "../src/systems.rs"] mod systems;
+#[path = "../src/wave.rs"] mod wave;
+
+// wave.rs finds this one at the crate root, same as in main.rs
+pub fn variant_eq<T>(a: &T, b: &T) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+use bullet::{BulletHurtType, BulletKind};
+use constants::*;
+use ecs::{Collider, Entity, Projectile, Transform, World};
+use spatial_hash::SpatialHash;
+
+
+const BULLET_COUNTS: [usize; 5] = [100, 250, 500, 1000, 2000];
+
+
+// bullet sized colliders scattered over the playfield, half of them the player's, each moved one 60fps frame
+fn scatter(count: usize) -> World {
+    rand::srand(count as u64);
+    let mut world = World::new();
+    for n in 0..count {
+        let pos = vec2(rand::gen_range(0f32, GAME_SIZE_X as f32), rand::gen_range(0f32, GAME_SIZE_Y as f32));
+        let vel = vec2(rand::gen_range(-60f32, 60f32), rand::gen_range(-120f32, 120f32));
+        let mut collider = Collider::at(pos, vec2(0f32, 0f32), vec2(3f32, 5f32));
+        collider.prev_rect = collider.rect_at(pos - vel / 60f32);
+
+        let entity = world.spawn();
+        world.insert_transform(entity, Transform { pos, rotation: 0f32 });
+        world.insert_collider(entity, collider);
+        world.insert_projectile(entity, Projectile {
+            hurt_type: if n % 2 == 0 { BulletHurtType::Enermy } else { BulletHurtType::Player },
+            kind: BulletKind::Missile,
+            is_kill: false,
+            grazed: false
+        });
+    }
+    world
+}
+
+
+// every pair of bullets whose swept rects overlap, found through the hash broadphase fills
+fn hashed_pairs(world: &World, bullet_hash: &mut SpatialHash<Entity>, enermy_hash: &mut SpatialHash<Entity>, nearby: &mut Vec<Entity>) -> usize {
+    systems::broadphase(world, bullet_hash, enermy_hash);
+    let mut pairs = 0;
+    for entity in world.entities.iter() {
+        let rect = world.colliders.get(entity).unwrap().swept_rect();
+        bullet_hash.query(&rect, nearby);
+        pairs += nearby.iter()
+            .filter(|other| **other > entity && rect.overlaps(&world.colliders.get(**other).unwrap().swept_rect()))
+            .count();
+    }
+    pairs
+}
+
+
+fn nested_loop_pairs(world: &World) -> usize {
+    let rects: Vec<Rect> = world.entities.iter().map(|entity| world.colliders.get(entity).unwrap().swept_rect()).collect();
+    let mut pairs = 0;
+    for (n, rect) in rects.iter().enumerate() {
+        pairs += rects[n + 1..].iter().filter(|other| rect.overlaps(other)).count();
+    }
+    pairs
+}
+
+
+fn broadphase(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bullet pairs");
+    let mut bullet_hash = SpatialHash::new(SPATIAL_HASH_CELL_SIZE);
+    let mut enermy_hash = SpatialHash::new(SPATIAL_HASH_CELL_SIZE);
+    let mut nearby = Vec::new();
+    for count in BULLET_COUNTS.iter() {
+        let world = scatter(*count);
+        assert_eq!(hashed_pairs(&world, &mut bullet_hash, &mut enermy_hash, &mut nearby), nested_loop_pairs(&world));
+
+        group.bench_with_input(BenchmarkId::new("broadphase", count), &world, |b, world| {
+            b.iter(|| hashed_pairs(world, &mut bullet_hash, &mut enermy_hash, &mut nearby))
+        });
+        group.bench_with_input(BenchmarkId::new("nested loop", count), &world, |b, world| {
+            b.iter(|| nested_loop_pairs(world))
+        });
+    }
+    group.finish();
+}
+
+
+criterion_group!(benches, broadphase);
+criterion_main!(benches);
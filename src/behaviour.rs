@@ -0,0 +1,378 @@
+use macroquad::prelude::*;
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    bullet_pattern::{self, PatternRunner, PatternValue},
+    sexpr::{self, Sexpr}
+};
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BehaviourStatus {
+    Running,
+    Success,
+    Failure
+}
+
+
+// the leaves of a tree, carried out by a BehaviourActor
+pub enum BehaviourAction {
+    Wait(PatternValue), // holds still
+    Wander(Option<PatternValue>), // follows the path and dodges, None lasts the profile shoot-time
+    MoveTo(Vec2, Option<f32>), // None moves at the profile speed
+    ShootPattern(Option<String>), // None shoots the pattern picked on spawn, fails without one
+    Home, // winds up and charges the player, never finishes
+    Flee(PatternValue), // backs away from the player for a while
+    Dash, // locks on to the player and dashes through, never finishes
+    Snipe // aims a laser at the player, then fires one fast shot along it
+}
+
+
+pub enum BehaviourNode {
+    Sequence(Vec<BehaviourNode>), // runs children in order, stops at the first failure
+    Selector(Vec<BehaviourNode>), // runs children in order, stops at the first success
+    Repeat(Option<PatternValue>, Box<BehaviourNode>), // None repeats forever
+    Chance(f32), // succeeds with this probability
+    Action(BehaviourAction)
+}
+
+
+// scratch space of a running leaf, starts out empty every time the leaf starts over
+#[derive(Default)]
+pub struct ActionState {
+    pub started: bool,
+    pub timer: f32,
+    pub vel: Vec2,
+    pub target: Vec2,
+    pub count: i32,
+    pub runner_optional: Option<PatternRunner>
+}
+
+
+impl ActionState {
+    pub fn count_down(&mut self, dt: f32) -> BehaviourStatus {
+        self.timer -= dt;
+        if self.timer <= 0f32 { BehaviourStatus::Success } else { BehaviourStatus::Running }
+    }
+}
+
+
+pub trait BehaviourActor {
+    fn run(&mut self, action: &BehaviourAction, state: &mut ActionState, dt: f32) -> BehaviourStatus;
+}
+
+
+// runtime state that mirrors the part of the tree that is currently running
+enum BehaviourTask {
+    Composite(usize, Box<BehaviourTask>), // index of the running child
+    Repeat(i32, Box<BehaviourTask>), // iterations left, negative repeats forever
+    Idle,
+    Action(ActionState)
+}
+
+
+fn start(node: &BehaviourNode) -> BehaviourTask {
+    match node {
+        BehaviourNode::Sequence(children) | BehaviourNode::Selector(children) => {
+            BehaviourTask::Composite(0, Box::new(start(&children[0])))
+        }
+        BehaviourNode::Repeat(count_optional, child) => {
+            let count = count_optional.as_ref().map(|count| (count.eval() as i32).max(1)).unwrap_or(-1);
+            BehaviourTask::Repeat(count, Box::new(start(child)))
+        }
+        BehaviourNode::Chance(_) => BehaviourTask::Idle,
+        BehaviourNode::Action(_) => BehaviourTask::Action(ActionState::default())
+    }
+}
+
+
+fn tick(node: &BehaviourNode, task: &mut BehaviourTask, actor: &mut dyn BehaviourActor, dt: f32) -> BehaviourStatus {
+    match (node, task) {
+        (BehaviourNode::Sequence(children), BehaviourTask::Composite(index, child)) => loop {
+            match tick(&children[*index], child, actor, dt) {
+                BehaviourStatus::Success if *index + 1 < children.len() => {
+                    *index += 1;
+                    **child = start(&children[*index]);
+                }
+                status => return status
+            }
+        },
+        (BehaviourNode::Selector(children), BehaviourTask::Composite(index, child)) => loop {
+            match tick(&children[*index], child, actor, dt) {
+                BehaviourStatus::Failure if *index + 1 < children.len() => {
+                    *index += 1;
+                    **child = start(&children[*index]);
+                }
+                status => return status
+            }
+        },
+        (BehaviourNode::Repeat(_, child_node), BehaviourTask::Repeat(count, child)) => {
+            if tick(child_node, child, actor, dt) == BehaviourStatus::Running {
+                return BehaviourStatus::Running;
+            }
+            if *count > 0 {
+                *count -= 1;
+                if *count == 0 {
+                    return BehaviourStatus::Success;
+                }
+            }
+            // the next round starts next frame, so a child that finishes instantly can't hang the game
+            **child = start(child_node);
+            BehaviourStatus::Running
+        }
+        (BehaviourNode::Chance(chance), _) => {
+            if rand::gen_range(0f32, 1f32) < *chance { BehaviourStatus::Success } else { BehaviourStatus::Failure }
+        }
+        (BehaviourNode::Action(action), BehaviourTask::Action(state)) => actor.run(action, state, dt),
+        _ => unreachable!("behaviour task out of step with its tree")
+    }
+}
+
+
+fn running_action<'a>(node: &'a BehaviourNode, task: &'a BehaviourTask) -> Option<(&'a BehaviourAction, &'a ActionState)> {
+    match (node, task) {
+        (BehaviourNode::Sequence(children), BehaviourTask::Composite(index, child))
+        | (BehaviourNode::Selector(children), BehaviourTask::Composite(index, child)) => running_action(&children[*index], child),
+        (BehaviourNode::Repeat(_, child_node), BehaviourTask::Repeat(_, child)) => running_action(child_node, child),
+        (BehaviourNode::Action(action), BehaviourTask::Action(state)) => Some((action, state)),
+        _ => None
+    }
+}
+
+
+// one demon working through a tree, holds on to the last status once the root is done
+pub struct BehaviourRunner {
+    tree: Rc<BehaviourNode>,
+    task: BehaviourTask,
+    status: BehaviourStatus
+}
+
+
+impl BehaviourRunner {
+    pub fn update(&mut self, actor: &mut dyn BehaviourActor, dt: f32) -> BehaviourStatus {
+        if self.status == BehaviourStatus::Running {
+            self.status = tick(&self.tree, &mut self.task, actor, dt);
+        }
+        self.status
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status != BehaviourStatus::Running
+    }
+
+    // the running leaf along with its scratch space, for actions that go through phases
+    pub fn running_action_state(&self) -> Option<(&BehaviourAction, &ActionState)> {
+        if self.is_finished() {
+            return None;
+        }
+        running_action(&self.tree, &self.task)
+    }
+}
+
+
+pub struct BehaviourLibrary {
+    trees: HashMap<String, Rc<BehaviourNode>>
+}
+
+
+impl BehaviourLibrary {
+    pub fn new() -> Self {
+        BehaviourLibrary {
+            trees: HashMap::new()
+        }
+    }
+
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        for expr in sexpr::parse(source)?.iter() {
+            let (head, args) = expr.as_call()?;
+            if head != "tree" {
+                return Err(format!("expected `tree`, found `{}`", head));
+            }
+
+            let (name, root) = match args {
+                [name, root] => (name.as_atom()?, root),
+                _ => return Err(String::from("expected (tree name node)"))
+            };
+            let tree = compile_node(root).map_err(|e| format!("tree `{}`: {}", name, e))?;
+            self.trees.insert(String::from(name), Rc::new(tree));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.trees.contains_key(name)
+    }
+
+    // patterns named by shoot-pattern nodes, so they can be checked on startup
+    pub fn pattern_names(&self) -> Vec<&str> {
+        let mut names = Vec::<&str>::new();
+        for tree in self.trees.values() {
+            collect_pattern_names(tree, &mut names);
+        }
+        names
+    }
+
+    pub fn runner(&self, name: &str) -> Option<BehaviourRunner> {
+        self.trees.get(name).map(|tree| BehaviourRunner {
+            tree: tree.clone(),
+            task: start(tree),
+            status: BehaviourStatus::Running
+        })
+    }
+}
+
+
+fn collect_pattern_names<'a>(node: &'a BehaviourNode, names: &mut Vec<&'a str>) {
+    match node {
+        BehaviourNode::Sequence(children) | BehaviourNode::Selector(children) => {
+            for child in children.iter() {
+                collect_pattern_names(child, names);
+            }
+        }
+        BehaviourNode::Repeat(_, child) => collect_pattern_names(child, names),
+        BehaviourNode::Action(BehaviourAction::ShootPattern(Some(name))) => names.push(name),
+        _ => {}
+    }
+}
+
+
+fn compile_children(exprs: &[Sexpr]) -> Result<Vec<BehaviourNode>, String> {
+    if exprs.is_empty() {
+        return Err(String::from("sequence and selector need at least one child"));
+    }
+    exprs.iter().map(compile_node).collect()
+}
+
+
+fn compile_node(expr: &Sexpr) -> Result<BehaviourNode, String> {
+    let node = match expr.as_call()? {
+        ("sequence", children) => BehaviourNode::Sequence(compile_children(children)?),
+        ("selector", children) => BehaviourNode::Selector(compile_children(children)?),
+        ("repeat", [child]) => BehaviourNode::Repeat(None, Box::new(compile_node(child)?)),
+        ("repeat", [count, child]) => {
+            BehaviourNode::Repeat(Some(bullet_pattern::compile_value(count)?), Box::new(compile_node(child)?))
+        }
+        ("chance", [chance]) => BehaviourNode::Chance(chance.as_f32()?),
+        ("wait", [time]) => BehaviourNode::Action(BehaviourAction::Wait(bullet_pattern::compile_value(time)?)),
+        ("wander", []) => BehaviourNode::Action(BehaviourAction::Wander(None)),
+        ("wander", [time]) => BehaviourNode::Action(BehaviourAction::Wander(Some(bullet_pattern::compile_value(time)?))),
+        ("move-to", [x, y]) => BehaviourNode::Action(BehaviourAction::MoveTo(vec2(x.as_f32()?, y.as_f32()?), None)),
+        ("move-to", [x, y, speed]) => {
+            BehaviourNode::Action(BehaviourAction::MoveTo(vec2(x.as_f32()?, y.as_f32()?), Some(speed.as_f32()?)))
+        }
+        ("shoot-pattern", []) => BehaviourNode::Action(BehaviourAction::ShootPattern(None)),
+        ("shoot-pattern", [name]) => BehaviourNode::Action(BehaviourAction::ShootPattern(Some(String::from(name.as_atom()?)))),
+        ("home", []) => BehaviourNode::Action(BehaviourAction::Home),
+        ("flee", [time]) => BehaviourNode::Action(BehaviourAction::Flee(bullet_pattern::compile_value(time)?)),
+        ("dash", []) => BehaviourNode::Action(BehaviourAction::Dash),
+        ("snipe", []) => BehaviourNode::Action(BehaviourAction::Snipe),
+        (head, args) => return Err(format!("unknown node `{}` with {} arguments", head, args.len()))
+    };
+    Ok(node)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // waits count down, named patterns go off straight away and the spawn pattern is missing
+    #[derive(Default)]
+    struct Recorder {
+        log: Vec<String>
+    }
+
+    impl BehaviourActor for Recorder {
+        fn run(&mut self, action: &BehaviourAction, state: &mut ActionState, dt: f32) -> BehaviourStatus {
+            match action {
+                BehaviourAction::Wait(time) => {
+                    if !state.started {
+                        state.started = true;
+                        state.timer = time.eval();
+                        self.log.push(format!("wait {}", state.timer));
+                    }
+                    state.count_down(dt)
+                }
+                BehaviourAction::ShootPattern(Some(name)) => {
+                    self.log.push(format!("shoot {}", name));
+                    BehaviourStatus::Success
+                }
+                _ => BehaviourStatus::Failure
+            }
+        }
+    }
+
+    fn run(source: &str, frames: usize) -> (BehaviourStatus, Vec<String>) {
+        let mut library = BehaviourLibrary::new();
+        library.load(source).unwrap();
+        let mut runner = library.runner("test").unwrap();
+        let mut actor = Recorder::default();
+        let mut status = BehaviourStatus::Running;
+        for _ in 0..frames {
+            status = runner.update(&mut actor, 0.5f32);
+        }
+        (status, actor.log)
+    }
+
+    #[test]
+    fn sequence_stops_at_the_first_failure() {
+        let (status, log) = run("(tree test (sequence (shoot-pattern a) (wait 1) (shoot-pattern b)))", 1);
+        assert!(status == BehaviourStatus::Running);
+        assert_eq!(log, vec!["shoot a", "wait 1"]);
+
+        let (status, log) = run("(tree test (sequence (shoot-pattern a) (wait 1) (shoot-pattern b)))", 2);
+        assert!(status == BehaviourStatus::Success);
+        assert_eq!(log, vec!["shoot a", "wait 1", "shoot b"]);
+
+        let (status, log) = run("(tree test (sequence (shoot-pattern a) (shoot-pattern) (shoot-pattern b)))", 1);
+        assert!(status == BehaviourStatus::Failure);
+        assert_eq!(log, vec!["shoot a"]);
+    }
+
+    #[test]
+    fn selector_stops_at_the_first_success() {
+        let (status, log) = run("(tree test (selector (shoot-pattern) (shoot-pattern a) (shoot-pattern b)))", 1);
+        assert!(status == BehaviourStatus::Success);
+        assert_eq!(log, vec!["shoot a"]);
+    }
+
+    #[test]
+    fn repeat_runs_one_round_a_frame() {
+        let (status, log) = run("(tree test (repeat 3 (shoot-pattern a)))", 2);
+        assert!(status == BehaviourStatus::Running);
+        assert_eq!(log.len(), 2);
+
+        let (status, log) = run("(tree test (repeat 3 (shoot-pattern a)))", 10);
+        assert!(status == BehaviourStatus::Success);
+        assert_eq!(log.len(), 3);
+
+        // failures don't stop it either, and without a count it never finishes
+        let (status, log) = run("(tree test (repeat (selector (shoot-pattern) (wait 1))))", 10);
+        assert!(status == BehaviourStatus::Running);
+        assert_eq!(log.len(), 5);
+    }
+
+    #[test]
+    fn chance_succeeds_as_often_as_it_says() {
+        assert!(run("(tree test (chance 0))", 1).0 == BehaviourStatus::Failure);
+        assert!(run("(tree test (chance 1))", 1).0 == BehaviourStatus::Success);
+
+        rand::srand(3);
+        let (_, log) = run("(tree test (repeat 1000 (selector (sequence (chance 0.25) (shoot-pattern a)) (shoot-pattern b))))", 1000);
+        let hits = log.iter().filter(|entry| *entry == "shoot a").count();
+        assert_eq!(log.len(), 1000);
+        assert!(hits > 200 && hits < 300, "{} hits", hits);
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_wrong_arity() {
+        let load = |source: &str| BehaviourLibrary::new().load(source);
+        assert_eq!(load("(brain test (wait 1))").unwrap_err(), "expected `tree`, found `brain`");
+        assert_eq!(load("(tree test (wait 1) (wait 2))").unwrap_err(), "expected (tree name node)");
+        assert_eq!(load("(tree test (sequence))").unwrap_err(), "tree `test`: sequence and selector need at least one child");
+        assert_eq!(load("(tree test (teleport))").unwrap_err(), "tree `test`: unknown node `teleport` with 0 arguments");
+        assert_eq!(load("(tree test (wait))").unwrap_err(), "tree `test`: unknown node `wait` with 0 arguments");
+        assert_eq!(load("(tree test (repeat 2 3 (wait 1)))").unwrap_err(), "tree `test`: unknown node `repeat` with 3 arguments");
+        assert!(load("(tree test (chance often))").is_err());
+    }
+}
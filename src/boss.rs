@@ -0,0 +1,67 @@
+use macroquad::prelude::*;
+
+use crate::constants::*;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BossMovement {
+    Sweep,
+    FigureEight,
+    Dive
+}
+
+
+#[derive(Clone, Copy)]
+pub struct BossPhase {
+    pub health_threshold: f32, // phase starts once health drops to this fraction
+    pub movement: BossMovement,
+    pub speed: f32,
+    pub shoot_time: f32, // pause between runs of the pattern
+    pub pattern: &'static str
+}
+
+
+pub const BOSS_PHASES: [BossPhase; 3] = [
+    BossPhase {
+        health_threshold: 1f32,
+        movement: BossMovement::Sweep,
+        speed: 0.8f32,
+        shoot_time: 1.2f32,
+        pattern: "boss-spread"
+    },
+    BossPhase {
+        health_threshold: 0.6f32,
+        movement: BossMovement::FigureEight,
+        speed: 1.1f32,
+        shoot_time: 0.1f32,
+        pattern: "boss-twin"
+    },
+    BossPhase {
+        health_threshold: 0.25f32,
+        movement: BossMovement::Dive,
+        speed: 1.6f32,
+        shoot_time: 0.5f32,
+        pattern: "boss-rain"
+    },
+];
+
+
+pub fn phase_for_health(health: i32, max_health: i32) -> usize {
+    let fraction = health as f32 / max_health as f32;
+    BOSS_PHASES.iter()
+        .rposition(|phase| fraction <= phase.health_threshold)
+        .unwrap_or(0)
+}
+
+
+// where the boss should be, `time` is scaled by the phase speed already
+pub fn movement_target(movement: BossMovement, time: f32) -> Vec2 {
+    let (offset_x, offset_y) = match movement {
+        BossMovement::Sweep => (time.sin() * 80f32, 0f32),
+        BossMovement::FigureEight => (time.sin() * 80f32, (time * 2f32).sin() * 12f32),
+        BossMovement::Dive => ((time * 1.3f32).sin() * 90f32, (time * 0.7f32).sin().abs() * 35f32)
+    };
+
+    vec2(GAME_CENTER_X + offset_x, BOSS_HOVER_Y + offset_y)
+}
+
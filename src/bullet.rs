@@ -1,81 +1,125 @@
 
 use macroquad::prelude::*;
 
-use crate::{ resources::Resources, constants::* }; 
+use crate::{ 
+    resources::Resources, 
+    constants::*, 
+    ecs::{ Animation, Collider, Entity, Projectile, Sprite, Transform, World }, 
+    systems
+}; 
 
-#[derive(std::cmp::PartialEq)]
+#[derive(std::cmp::PartialEq, Clone, Copy)]
 pub enum BulletHurtType {
     Player, 
     Enermy
 }
 
-pub struct Bullet {
-    texture: Texture2D, 
-    pos: Vec2, 
-    vel: Vec2, 
-    pub hurt_type: BulletHurtType, 
-    anim_timer: f32,
-    pub collision_rect: Rect, 
-    pub is_kill: bool 
+// enemy bullet kinds, picked per fire spec in the pattern files
+#[derive(Clone, Copy, PartialEq)]
+pub enum BulletKind {
+    Missile, 
+    Orb // slow and soft, player bullets can shoot it down
 }
 
 
+impl BulletKind {
+    // as the data files spell it
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "missile" => Ok(BulletKind::Missile), 
+            "orb" => Ok(BulletKind::Orb), 
+            other => Err(format!("unknown bullet kind `{}`", other))
+        }
+    }
 
-impl Bullet {
+    pub fn is_shootable(&self) -> bool {
+        match self {
+            BulletKind::Missile => false, 
+            BulletKind::Orb => true
+        }
+    }
 
-    pub fn new (pos: Vec2, hurt_type: BulletHurtType, resources: &Resources) -> Self {
-        let (vel, texture) = match hurt_type {
-            BulletHurtType::Enermy => (
-                vec2(0f32, -1f32 * PLAYER_BULLET_SPEED),
-                resources.player_missle
-            ), 
-            BulletHurtType::Player => (vec2(0f32, ENERMY_BULLET_SPEED), resources.deamon_missle)
-           
-        }; 
+    pub fn tint(&self) -> Color {
+        match self {
+            BulletKind::Missile => WHITE, 
+            BulletKind::Orb => BULLET_ORB_TINT
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+pub struct BulletMotion {
+    pub vel: Vec2, 
+    pub acc: Vec2, 
+    pub acc_delay: f32, // acceleration only kicks in once this runs out
+    pub turn_speed: f32 // radians per second the heading turns, makes curving bullets
+}
 
-        Bullet {
-            pos, 
-            texture, 
+
+impl BulletMotion {
+    pub fn straight(vel: Vec2) -> Self {
+        BulletMotion {
             vel, 
-            hurt_type, 
-            anim_timer: 0f32, 
-            collision_rect: Rect::new(pos.x, pos.y, 2.0f32, 6f32), 
-            is_kill: false,
-            
+            acc: vec2(0f32, 0f32), 
+            acc_delay: 0f32, 
+            turn_speed: 0f32
         }
     }
+}
 
-    pub fn draw(&mut self) {
-        let frame = ((self.anim_timer / BULLET_ANIM_TIME_SPAWN * 3.0f32)) as i32; 
-        draw_texture_ex(
-            self.texture, 
-            self.pos.x, 
-            self.pos.y, 
-            WHITE, 
-            DrawTextureParams {
-                rotation: 0f32, 
-                source: Some(Rect::new(
-                    self.texture.width() /3f32  * frame as f32, 
-                    0f32, 
-                    self.texture.width() / 3f32, 
-                    self.texture.height()
-                )),
-                ..Default::default()
-            }
-        )
-    }
 
-    pub fn overlaps(&self, other_rect: &Rect) -> bool {
-        return self.collision_rect.overlaps(other_rect); 
+// the sprites point straight along the default direction of their hurt type
+fn sprite_forward(hurt_type: BulletHurtType) -> Vec2 {
+    match hurt_type {
+        BulletHurtType::Enermy => vec2(0f32, -1f32), 
+        BulletHurtType::Player => vec2(0f32, 1f32)
     }
+}
 
-    pub fn update(&mut self, dt: f32) {
-        self.pos += self.vel * dt; 
-        self.anim_timer += dt; 
-        self.collision_rect.x = self.pos.x; 
-        self.collision_rect.y = self.pos.y; 
 
-    }
+// flies straight along the default direction of its hurt type
+pub fn spawn(world: &mut World, pos: Vec2, hurt_type: BulletHurtType, resources: &Resources) -> Entity {
+    let vel = match hurt_type {
+        BulletHurtType::Enermy => vec2(0f32, -1f32 * PLAYER_BULLET_SPEED), 
+        BulletHurtType::Player => vec2(0f32, ENERMY_BULLET_SPEED)
+    }; 
+
+    spawn_moving(world, pos, BulletMotion::straight(vel), hurt_type, BulletKind::Missile, resources)
+}
 
 
-}
\ No newline at end of file
+pub fn spawn_moving(
+    world: &mut World, 
+    pos: Vec2, 
+    motion: BulletMotion, 
+    hurt_type: BulletHurtType, 
+    kind: BulletKind, 
+    resources: &Resources
+) -> Entity {
+    let texture = match hurt_type {
+        BulletHurtType::Enermy => resources.player_missle, 
+        BulletHurtType::Player => resources.deamon_missle
+    }; 
+    let forward = sprite_forward(hurt_type); 
+    let frame_count = 3f32; 
+    let frame_size = vec2(texture.width() / frame_count, texture.height()); 
+    let rect = systems::collider_rect(pos, frame_size, motion.vel); 
+
+    let entity = world.spawn(); 
+    world.insert_transform(entity, Transform {
+        pos, 
+        rotation: systems::heading_rotation(forward, motion.vel).unwrap_or(0f32)
+    }); 
+    world.insert_bullet_motion(entity, motion); 
+    world.insert_sprite(entity, Sprite {
+        texture, 
+        frame_count, 
+        tint: kind.tint(), 
+        forward
+    }); 
+    world.insert_animation(entity, Animation { timer: 0f32, loop_time_optional: None }); 
+    world.insert_collider(entity, Collider { frame_size, offset: vec2(0f32, 0f32), rect, prev_rect: rect }); 
+    world.insert_projectile(entity, Projectile { hurt_type, kind, is_kill: false, grazed: false }); 
+    entity
+}
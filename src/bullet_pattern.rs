@@ -0,0 +1,402 @@
+use macroquad::prelude::*;
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    constants::*,
+    resources::Resources,
+    bullet::{self, BulletHurtType, BulletKind, BulletMotion},
+    ecs::World,
+    sexpr::{self, Sexpr}
+};
+
+
+// numbers in a pattern are either fixed or rolled every time the action runs
+#[derive(Clone, Copy)]
+pub enum PatternValue {
+    Const(f32),
+    Rand(f32, f32)
+}
+
+
+impl PatternValue {
+    pub fn eval(&self) -> f32 {
+        match self {
+            PatternValue::Const(value) => *value,
+            PatternValue::Rand(min, max) => rand::gen_range(*min, *max)
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+pub enum PatternDirection {
+    Down,
+    Aim(f32, f32), // lead, spread in degrees
+    Absolute(PatternValue),
+    Sequence(PatternValue) // turns by this much every time the runner fires
+}
+
+
+#[derive(Clone, Copy)]
+pub enum PatternShape {
+    Single,
+    Fan(i32, f32),
+    Ring(i32)
+}
+
+
+#[derive(Clone, Copy)]
+pub struct PatternFire {
+    direction: PatternDirection,
+    shape: PatternShape,
+    speed: PatternValue,
+    accel: PatternValue,
+    accel_delay: PatternValue,
+    turn: PatternValue,
+    offset: Vec2,
+    kind: BulletKind
+}
+
+
+pub enum PatternOp {
+    Fire(PatternFire),
+    Wait(PatternValue),
+    RepeatStart(PatternValue, usize), // count, index of the matching RepeatEnd
+    RepeatEnd(usize) // index of the first op in the loop
+}
+
+
+pub struct BulletPatternLibrary {
+    patterns: HashMap<String, Rc<Vec<PatternOp>>>
+}
+
+
+impl BulletPatternLibrary {
+    pub fn new() -> Self {
+        BulletPatternLibrary {
+            patterns: HashMap::new()
+        }
+    }
+
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        for expr in sexpr::parse(source)?.iter() {
+            let (head, args) = expr.as_call()?;
+            if head != "pattern" {
+                return Err(format!("expected `pattern`, found `{}`", head));
+            }
+
+            let (name, actions) = args.split_first().ok_or("pattern without a name")?;
+            let name = name.as_atom()?;
+            let mut ops = Vec::<PatternOp>::new();
+            compile_actions(actions, &mut ops).map_err(|e| format!("pattern `{}`: {}", name, e))?;
+            self.patterns.insert(String::from(name), Rc::new(ops));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.patterns.contains_key(name)
+    }
+
+    pub fn runner(&self, name: &str) -> Option<PatternRunner> {
+        self.patterns.get(name).map(|ops| PatternRunner::new(ops.clone()))
+    }
+}
+
+
+pub fn compile_value(expr: &Sexpr) -> Result<PatternValue, String> {
+    if let Sexpr::Atom(_) = expr {
+        return Ok(PatternValue::Const(expr.as_f32()?));
+    }
+
+    match expr.as_call()? {
+        ("rand", [min, max]) => Ok(PatternValue::Rand(min.as_f32()?, max.as_f32()?)),
+        (head, _) => Err(format!("unknown value `{}`", head))
+    }
+}
+
+
+fn compile_fire(specs: &[Sexpr]) -> Result<PatternFire, String> {
+    let mut fire = PatternFire {
+        direction: PatternDirection::Down,
+        shape: PatternShape::Single,
+        speed: PatternValue::Const(60f32),
+        accel: PatternValue::Const(0f32),
+        accel_delay: PatternValue::Const(0f32),
+        turn: PatternValue::Const(0f32),
+        offset: vec2(0f32, 0f32),
+        kind: BulletKind::Missile
+    };
+
+    for spec in specs.iter() {
+        match spec.as_call()? {
+            ("speed", [value]) => fire.speed = compile_value(value)?,
+            ("accel", [value]) => fire.accel = compile_value(value)?,
+            ("accel", [value, delay]) => {
+                fire.accel = compile_value(value)?;
+                fire.accel_delay = compile_value(delay)?;
+            }
+            ("aim", []) => fire.direction = PatternDirection::Aim(ENERMY_AIM_LEAD, ENERMY_AIM_SPREAD),
+            ("aim", [lead, spread]) => fire.direction = PatternDirection::Aim(lead.as_f32()?, spread.as_f32()?),
+            ("turn", [value]) => fire.turn = compile_value(value)?,
+            ("dir", [value]) => fire.direction = PatternDirection::Absolute(compile_value(value)?),
+            ("seq", [value]) => fire.direction = PatternDirection::Sequence(compile_value(value)?),
+            ("fan", [count, spread]) => fire.shape = PatternShape::Fan(count.as_f32()? as i32, spread.as_f32()?),
+            ("ring", [count]) => fire.shape = PatternShape::Ring(count.as_f32()? as i32),
+            ("offset", [x, y]) => fire.offset = vec2(x.as_f32()?, y.as_f32()?),
+            ("kind", [kind]) => fire.kind = BulletKind::from_name(kind.as_atom()?)?,
+            (head, args) => return Err(format!("unknown fire spec `{}` with {} arguments", head, args.len()))
+        }
+    }
+    Ok(fire)
+}
+
+
+fn compile_actions(actions: &[Sexpr], ops: &mut Vec<PatternOp>) -> Result<(), String> {
+    for action in actions.iter() {
+        match action.as_call()? {
+            ("fire", specs) => ops.push(PatternOp::Fire(compile_fire(specs)?)),
+            ("wait", [value]) => ops.push(PatternOp::Wait(compile_value(value)?)),
+            ("repeat", [count, body @ ..]) => {
+                let count = compile_value(count)?;
+                let start_index = ops.len();
+                ops.push(PatternOp::RepeatStart(count, 0));
+                compile_actions(body, ops)?;
+                let end_index = ops.len();
+                ops.push(PatternOp::RepeatEnd(start_index + 1));
+                // patch the jump now that the end of the loop is known
+                ops[start_index] = PatternOp::RepeatStart(count, end_index);
+            }
+            (head, args) => return Err(format!("unknown action `{}` with {} arguments", head, args.len()))
+        }
+    }
+    Ok(())
+}
+
+
+// where a pattern fires from, who aimed shots go for and who its bullets hurt
+pub struct PatternAim {
+    pub origin: Vec2,
+    pub target: Vec2,
+    pub target_vel: Vec2, // aimed shots lead the target by it
+    pub hurt_type: BulletHurtType
+}
+
+
+// steps through a compiled pattern, one per shooter
+pub struct PatternRunner {
+    ops: Rc<Vec<PatternOp>>,
+    op_index: usize,
+    loops: Vec<i32>, // iterations left for every loop we are inside of
+    wait_timer: f32,
+    sequence_angle: f32
+}
+
+
+impl PatternRunner {
+    pub fn new(ops: Rc<Vec<PatternOp>>) -> Self {
+        PatternRunner {
+            ops,
+            op_index: 0,
+            loops: Vec::new(),
+            wait_timer: 0f32,
+            sequence_angle: 0f32
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.op_index >= self.ops.len()
+    }
+
+    // runs the pattern again, a trailing wait still has to run out first
+    pub fn restart(&mut self) {
+        self.op_index = 0;
+        self.loops.clear();
+    }
+
+    // returns how many bullets were spawned this frame
+    pub fn update(&mut self, dt: f32, aim: &PatternAim, world: &mut World, resources: &Resources) -> i32 {
+        self.wait_timer -= dt;
+        let mut bullets_fired = 0;
+        let ops = self.ops.clone();
+
+        while self.wait_timer <= 0f32 && !self.is_finished() {
+            match &ops[self.op_index] {
+                PatternOp::Fire(fire) => {
+                    bullets_fired += self.fire(fire, aim, world, resources);
+                    self.op_index += 1;
+                }
+                PatternOp::Wait(value) => {
+                    self.wait_timer += value.eval();
+                    self.op_index += 1;
+                }
+                PatternOp::RepeatStart(count, end_index) => {
+                    let count = count.eval().round() as i32;
+                    if count > 0 {
+                        self.loops.push(count);
+                        self.op_index += 1;
+                    } else {
+                        self.op_index = end_index + 1;
+                    }
+                }
+                PatternOp::RepeatEnd(start_index) => {
+                    let iterations_left = self.loops.last_mut().unwrap();
+                    *iterations_left -= 1;
+                    if *iterations_left > 0 {
+                        self.op_index = *start_index;
+                    } else {
+                        self.loops.pop();
+                        self.op_index += 1;
+                    }
+                }
+            }
+        }
+
+        if self.is_finished() {
+            self.wait_timer = self.wait_timer.max(0f32);
+        }
+        bullets_fired
+    }
+
+    fn fire(&mut self, fire: &PatternFire, aim: &PatternAim, world: &mut World, resources: &Resources) -> i32 {
+        let pos = aim.origin + fire.offset;
+        let base_angle = match fire.direction {
+            PatternDirection::Down => 0f32,
+            PatternDirection::Aim(lead, spread) => {
+                // lead the target by where it will be once the bullet gets there
+                let bullet_speed = fire.speed.eval().max(1f32);
+                let time_to_target = (aim.target - pos).length() / bullet_speed;
+                let predicted_target = aim.target + aim.target_vel * time_to_target * lead;
+                let to_target = predicted_target - pos;
+                let spread_offset = if spread > 0f32 {
+                    rand::gen_range(-spread * 0.5f32, spread * 0.5f32)
+                } else {
+                    0f32
+                };
+                to_target.x.atan2(to_target.y).to_degrees() + spread_offset
+            }
+            PatternDirection::Absolute(value) => value.eval(),
+            PatternDirection::Sequence(value) => {
+                self.sequence_angle += value.eval();
+                self.sequence_angle
+            }
+        };
+
+        let angles: Vec<f32> = match fire.shape {
+            PatternShape::Single => vec![base_angle],
+            PatternShape::Fan(count, spread) => {
+                if count <= 1 {
+                    vec![base_angle]
+                } else {
+                    (0..count)
+                        .map(|i| base_angle - spread * 0.5f32 + spread * i as f32 / (count - 1) as f32)
+                        .collect()
+                }
+            }
+            PatternShape::Ring(count) => (0..count)
+                .map(|i| base_angle + 360f32 * i as f32 / count as f32)
+                .collect()
+        };
+
+        for angle in angles.iter() {
+            let radians = angle.to_radians();
+            // 0 degrees is straight down, positive turns towards +x
+            let dir = vec2(radians.sin(), radians.cos());
+            let motion = BulletMotion {
+                vel: dir * fire.speed.eval(),
+                acc: dir * fire.accel.eval(),
+                acc_delay: fire.accel_delay.eval(),
+                // positive angles turn towards +x, which is clockwise on screen
+                turn_speed: -fire.turn.eval().to_radians()
+            };
+            bullet::spawn_moving(world, pos, motion, aim.hurt_type, fire.kind, resources);
+        }
+        angles.len() as i32
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::load_resources_headless;
+
+    fn load(source: &str) -> Result<BulletPatternLibrary, String> {
+        let mut library = BulletPatternLibrary::new();
+        library.load(source)?;
+        Ok(library)
+    }
+
+    fn aim() -> PatternAim {
+        PatternAim {
+            origin: vec2(100f32, 50f32),
+            target: vec2(100f32, 250f32),
+            target_vel: vec2(0f32, 0f32),
+            hurt_type: BulletHurtType::Player
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_wrong_arity() {
+        assert_eq!(load("(volley burst (fire))").err().unwrap(), "expected `pattern`, found `volley`");
+        assert_eq!(load("(pattern)").err().unwrap(), "pattern without a name");
+        assert_eq!(load("(pattern burst (spin 3))").err().unwrap(), "pattern `burst`: unknown action `spin` with 1 arguments");
+        assert_eq!(load("(pattern burst (wait 1 2))").err().unwrap(), "pattern `burst`: unknown action `wait` with 2 arguments");
+        assert_eq!(load("(pattern burst (fire (ring 8 2)))").err().unwrap(), "pattern `burst`: unknown fire spec `ring` with 2 arguments");
+        assert_eq!(load("(pattern burst (fire (speed (roll 1 2))))").err().unwrap(), "pattern `burst`: unknown value `roll`");
+        assert!(load("(pattern burst (fire (kind laser)))").is_err());
+        assert!(load("(pattern burst (fire (ring 8))").is_err());
+    }
+
+    #[test]
+    fn repeats_fire_on_the_wait_timer() {
+        let resources = load_resources_headless();
+        let library = load("(pattern burst (repeat 3 (fire (ring 4)) (wait 0.5)) (fire (fan 5 60)))").unwrap();
+        let mut runner = library.runner("burst").unwrap();
+        let mut world = World::new();
+
+        // the first ring goes off straight away, then one every half second
+        assert_eq!(runner.update(0f32, &aim(), &mut world, &resources), 4);
+        assert_eq!(runner.update(0.25f32, &aim(), &mut world, &resources), 0);
+        assert_eq!(runner.update(0.25f32, &aim(), &mut world, &resources), 4);
+        assert_eq!(runner.update(0.5f32, &aim(), &mut world, &resources), 4);
+        assert!(!runner.is_finished());
+        // the fan only comes once the last wait in the loop runs out
+        assert_eq!(runner.update(0.5f32, &aim(), &mut world, &resources), 5);
+        assert!(runner.is_finished());
+        assert_eq!(runner.update(1f32, &aim(), &mut world, &resources), 0);
+        assert_eq!(world.entities.live_count(), 17);
+
+        runner.restart();
+        assert_eq!(runner.update(0f32, &aim(), &mut world, &resources), 4);
+    }
+
+    #[test]
+    fn catches_up_on_a_long_frame() {
+        let resources = load_resources_headless();
+        let library = load("(pattern stream (repeat 10 (fire) (wait 0.1)))").unwrap();
+        let mut runner = library.runner("stream").unwrap();
+        let mut world = World::new();
+
+        assert_eq!(runner.update(0f32, &aim(), &mut world, &resources), 1);
+        assert_eq!(runner.update(0.35f32, &aim(), &mut world, &resources), 3);
+        assert_eq!(runner.update(1f32, &aim(), &mut world, &resources), 6);
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn aims_at_the_target() {
+        let resources = load_resources_headless();
+        let library = load("(pattern sideways (fire (dir 90) (speed 80)) (fire (aim 0 0) (speed 80)))").unwrap();
+        let mut runner = library.runner("sideways").unwrap();
+        let mut world = World::new();
+        let target = vec2(300f32, 50f32);
+        let aim = PatternAim { target, ..aim() };
+
+        assert_eq!(runner.update(0f32, &aim, &mut world, &resources), 2);
+        for entity in world.entities.iter() {
+            let vel = world.bullet_motions.get(entity).unwrap().vel;
+            assert!((vel - vec2(80f32, 0f32)).length() < 0.001f32, "{:?}", vel);
+        }
+    }
+}
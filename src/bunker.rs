@@ -0,0 +1,234 @@
+use macroquad::prelude::*;
+
+use crate::{
+    constants::*,
+    resources::Resources
+};
+
+
+// # is solid, the notch at the bottom is where the player tucks in
+const BUNKER_SHAPE: [&str; 8] = [
+    "...##########...",
+    "..############..",
+    ".##############.",
+    "################",
+    "################",
+    "################",
+    "#####......#####",
+    "####........####",
+];
+
+
+// a block of cover that gets shot away one pixel at a time
+pub struct Bunker {
+    pos: Vec2, // top left
+    mask: Vec<bool>, // one entry per pixel, row by row, true is still standing
+    image: Image,
+    texture_optional: Option<Texture2D>, // made on the first draw, so the game runs without a window in the replay tests
+    dirty: bool // the texture is behind the mask
+}
+
+
+impl Bunker {
+    fn new(pos: Vec2) -> Self {
+        let image = Image::gen_image_color(Self::width() as u16, Self::height() as u16, BLANK);
+
+        let mut bunker = Bunker {
+            pos,
+            mask: Vec::new(),
+            image,
+            texture_optional: None,
+            dirty: true
+        };
+        bunker.rebuild();
+        bunker
+    }
+
+    fn width() -> i32 {
+        BUNKER_SHAPE[0].len() as i32
+    }
+
+    fn height() -> i32 {
+        BUNKER_SHAPE.len() as i32
+    }
+
+    fn rebuild(&mut self) {
+        self.mask = BUNKER_SHAPE.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        self.dirty = true;
+    }
+
+    fn is_solid(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < Self::width() && y < Self::height() && self.mask[(y * Self::width() + x) as usize]
+    }
+
+    // the standing pixel under rect closest to its center, in bunker pixels
+    fn first_hit(&self, rect: &Rect) -> Option<(i32, i32)> {
+        let left = ((rect.x - self.pos.x).floor() as i32).max(0);
+        let top = ((rect.y - self.pos.y).floor() as i32).max(0);
+        let right = ((rect.x + rect.w - self.pos.x).ceil() as i32).min(Self::width());
+        let bottom = ((rect.y + rect.h - self.pos.y).ceil() as i32).min(Self::height());
+        let center = rect.point() + rect.size() * 0.5f32 - self.pos;
+
+        let mut hit_optional: Option<((i32, i32), f32)> = None;
+        for y in top..bottom {
+            for x in left..right {
+                if !self.is_solid(x, y) {
+                    continue;
+                }
+                let distance = vec2(x as f32 + 0.5f32, y as f32 + 0.5f32).distance(center);
+                let closer = match hit_optional {
+                    Some((_, best)) => distance < best,
+                    None => true
+                };
+                if closer {
+                    hit_optional = Some(((x, y), distance));
+                }
+            }
+        }
+        hit_optional.map(|(hit, _)| hit)
+    }
+
+    // knocks out a ragged crater, the middle always goes and the edge only partly
+    fn chip(&mut self, (hit_x, hit_y): (i32, i32), radius: f32) {
+        let reach = radius.ceil() as i32;
+        for y in (hit_y - reach)..=(hit_y + reach) {
+            for x in (hit_x - reach)..=(hit_x + reach) {
+                if !self.is_solid(x, y) {
+                    continue;
+                }
+                let distance = vec2((x - hit_x) as f32, (y - hit_y) as f32).length();
+                if distance <= radius * 0.5f32 || (distance <= radius && rand::gen_range(0f32, 1f32) < 0.5f32) {
+                    self.mask[(y * Self::width() + x) as usize] = false;
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    fn draw(&mut self) {
+        let image = &self.image;
+        let texture = *self.texture_optional.get_or_insert_with(|| {
+            let texture = Texture2D::from_image(image);
+            texture.set_filter(FilterMode::Nearest);
+            texture
+        });
+        if self.dirty {
+            for y in 0..Self::height() {
+                for x in 0..Self::width() {
+                    let color = if self.is_solid(x, y) { BUNKER_COLOR } else { BLANK };
+                    self.image.set_pixel(x as u32, y as u32, color);
+                }
+            }
+            texture.update(&self.image);
+            self.dirty = false;
+        }
+        draw_texture(texture, self.pos.x, self.pos.y, WHITE);
+    }
+}
+
+
+// the row of bunkers between the player and the demons
+pub struct BunkerManager {
+    bunkers: Vec<Bunker>
+}
+
+
+impl BunkerManager {
+    pub fn new(resources: &Resources) -> Self {
+        let bottom = GAME_SIZE_Y as f32 - resources.ground_bg.height() - resources.player.height() - BUNKER_PLAYER_GAP;
+        let bunkers = (0..BUNKER_COUNT)
+            .map(|i| {
+                let center_x = GAME_SIZE_X as f32 * (i + 1) as f32 / (BUNKER_COUNT + 1) as f32;
+                Bunker::new(vec2(
+                    (center_x - Bunker::width() as f32 * 0.5f32).floor(),
+                    bottom - Bunker::height() as f32
+                ))
+            })
+            .collect();
+
+        BunkerManager { bunkers }
+    }
+
+    // brings every bunker back to full shape, done between waves
+    pub fn reset(&mut self) {
+        for bunker in self.bunkers.iter_mut() {
+            bunker.rebuild();
+        }
+    }
+
+    // chips whatever standing pixels rect touches first, true if it hit something
+    pub fn hit(&mut self, rect: &Rect, radius: f32) -> bool {
+        for bunker in self.bunkers.iter_mut() {
+            if let Some(hit) = bunker.first_hit(rect) {
+                bunker.chip(hit, radius);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn draw(&mut self) {
+        for bunker in self.bunkers.iter_mut() {
+            bunker.draw();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(bunker: &Bunker) -> usize {
+        bunker.mask.iter().filter(|solid| **solid).count()
+    }
+
+    // a 3x5 bullet with its top left at x, y in bunker pixels
+    fn bullet_at(x: f32, y: f32) -> Rect {
+        Rect::new(x, y, 3f32, 5f32)
+    }
+
+    #[test]
+    fn misses_the_notch_and_the_corners() {
+        let bunker = Bunker::new(vec2(0f32, 0f32));
+        assert_eq!(bunker.first_hit(&Rect::new(6f32, 6f32, 4f32, 2f32)), None);
+        assert_eq!(bunker.first_hit(&Rect::new(0f32, 0f32, 2f32, 1f32)), None);
+        assert_eq!(bunker.first_hit(&bullet_at(-10f32, 0f32)), None);
+        assert_eq!(bunker.first_hit(&bullet_at(0f32, 2f32)), Some((1, 4)));
+    }
+
+    #[test]
+    fn chips_a_crater_around_the_hit() {
+        rand::srand(1);
+        let mut bunker = Bunker::new(vec2(0f32, 0f32));
+        let full = standing(&bunker);
+        let hit = bunker.first_hit(&bullet_at(7f32, -4f32)).unwrap();
+        assert_eq!(hit, (8, 0));
+
+        bunker.chip(hit, BUNKER_CHIP_RADIUS);
+        assert!(!bunker.is_solid(8, 0));
+        assert!(!bunker.is_solid(8, 1));
+        assert!(bunker.is_solid(8, 4));
+        let chipped = full - standing(&bunker);
+        assert!((5..=21).contains(&chipped), "{} pixels chipped", chipped);
+
+        bunker.rebuild();
+        assert_eq!(standing(&bunker), full);
+    }
+
+    #[test]
+    fn shots_in_one_spot_bore_through() {
+        rand::srand(2);
+        let mut bunker = Bunker::new(vec2(40f32, 100f32));
+        // a swept rect reaching through the whole bunker, the column above the notch
+        let swept = Rect::new(40f32 + 7f32, 100f32 - 4f32, 3f32, 16f32);
+        let mut hits = 0;
+        while let Some(hit) = bunker.first_hit(&swept) {
+            bunker.chip(hit, BUNKER_CHIP_RADIUS);
+            hits += 1;
+            assert!(hits < 10, "still standing after {} hits", hits);
+        }
+        assert!(hits >= 2);
+        assert!(standing(&bunker) > 0);
+    }
+}
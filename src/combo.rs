@@ -0,0 +1,121 @@
+use crate::constants::*; 
+
+
+// tracks consecutive kills, the chain drops if the player doesn't kill again within COMBO_CHAIN_TIME
+pub struct Combo {
+    pub chain: i32, 
+    pub timer: f32
+}
+
+
+impl Combo {
+    pub fn new() -> Self {
+        Combo {
+            chain: 0, 
+            timer: 0f32
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.chain = 0; 
+        self.timer = 0f32; 
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.timer <= 0f32 {
+            return; 
+        }
+
+        self.timer -= dt; 
+        if self.timer <= 0f32 {
+            self.reset(); 
+        }
+    }
+
+    // returns the chain length including this kill
+    pub fn register_kill(&mut self) -> i32 {
+        self.chain += 1; 
+        self.timer = COMBO_CHAIN_TIME; 
+        self.chain
+    }
+
+    pub fn register_miss(&mut self) {
+        self.chain = (self.chain - COMBO_MISS_PENALTY).max(0); 
+        if self.chain == 0 {
+            self.timer = 0f32; 
+        }
+    }
+
+    pub fn register_damage(&mut self) {
+        self.reset(); 
+    }
+
+    pub fn multiplier(&self) -> i32 {
+        (1 + self.chain / COMBO_KILLS_PER_MULTIPLIER).min(COMBO_MULTIPLIER_MAX)
+    }
+
+    // 0..1, how much time is left before the chain drops
+    pub fn timer_fraction(&self) -> f32 {
+        (self.timer / COMBO_CHAIN_TIME).max(0f32)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain_of(kills: i32) -> Combo {
+        let mut combo = Combo::new();
+        for _ in 0..kills {
+            combo.register_kill();
+        }
+        combo
+    }
+
+    #[test]
+    fn multiplier_grows_with_the_chain_up_to_the_cap() {
+        assert_eq!(chain_of(0).multiplier(), 1);
+        assert_eq!(chain_of(COMBO_KILLS_PER_MULTIPLIER - 1).multiplier(), 1);
+        assert_eq!(chain_of(COMBO_KILLS_PER_MULTIPLIER).multiplier(), 2);
+        assert_eq!(chain_of(COMBO_KILLS_PER_MULTIPLIER * 100).multiplier(), COMBO_MULTIPLIER_MAX);
+    }
+
+    #[test]
+    fn chain_drops_once_the_timer_runs_out() {
+        let mut combo = chain_of(COMBO_KILLS_PER_MULTIPLIER);
+        combo.update(COMBO_CHAIN_TIME * 0.9f32);
+        assert_eq!(combo.multiplier(), 2);
+        assert!(combo.timer_fraction() > 0f32 && combo.timer_fraction() < 0.2f32);
+
+        // another kill winds the timer back up
+        combo.register_kill();
+        combo.update(COMBO_CHAIN_TIME * 0.9f32);
+        assert_eq!(combo.chain, COMBO_KILLS_PER_MULTIPLIER + 1);
+
+        combo.update(COMBO_CHAIN_TIME * 0.2f32);
+        assert_eq!(combo.chain, 0);
+        assert_eq!(combo.multiplier(), 1);
+        assert_eq!(combo.timer_fraction(), 0f32);
+    }
+
+    #[test]
+    fn misses_shorten_the_chain_and_damage_ends_it() {
+        let mut combo = chain_of(COMBO_KILLS_PER_MULTIPLIER);
+        combo.register_miss();
+        assert_eq!(combo.chain, COMBO_KILLS_PER_MULTIPLIER - COMBO_MISS_PENALTY);
+        assert_eq!(combo.multiplier(), 1);
+        assert!(combo.timer > 0f32);
+
+        for _ in 0..COMBO_KILLS_PER_MULTIPLIER {
+            combo.register_miss();
+        }
+        assert_eq!(combo.chain, 0);
+        assert_eq!(combo.timer, 0f32);
+
+        let mut combo = chain_of(COMBO_KILLS_PER_MULTIPLIER * 3);
+        combo.register_damage();
+        assert_eq!(combo.chain, 0);
+        assert_eq!(combo.multiplier(), 1);
+    }
+}
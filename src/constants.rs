@@ -1,5 +1,7 @@
 use macroquad::prelude::*;
 
+use crate::enermy::EnermyType;
+
 
 pub const GAME_SIZE_X: i32 = 240;
 pub const GAME_SIZE_Y: i32 = 130;
@@ -9,33 +11,156 @@ pub const PLAYER_LIVES_START:i32 = 5;
 pub const PLAYER_TIME_INVISBLE: f32 = 2f32;
 pub const ENERMY_MINI_ANIM_TIME_SPAWN:f32 = 0.3f32;
 pub const ENERMY_ANIM_TIME_FLAP:f32 =  0.12f32; 
-pub const ENERMY_MINI_HOMING_TIME_RANGE: Vec2 = const_vec2!([4f32, 10f32]); 
+pub const ENERMY_SPRITE_FRAMES: i32 = 4; // flap frames side by side in every demon sheet
 pub const ENEMY_ANIM_DISTANCE: f32 = 140f32;
 pub const ENEMY_ANIM_TIME_SPAWN: f32 = 0.7f32; 
-pub const ENERMY_SPEED: f32 = 50.0f32;
 pub const PLAYER_SHOOT_TIME:f32 = 0.12f32; //check here again
 pub const PLAYER_SPEED:f32 = 90f32;
+pub const PLAYER_HITBOX_OFFSET: Vec2 = const_vec2!([2f32, 1f32]); // core hitbox, relative to the sprite origin
+pub const PLAYER_HITBOX_SIZE: f32 = 3f32;
+pub const PLAYER_GRAZE_RADIUS: f32 = 10f32;
+pub const PLAYER_GRAZE_METER_PER_BULLET: f32 = 0.05f32;
+pub const SCORE_GRAZE: i32 = 10;
+pub const SCORE_GRAZE_BURST_BULLET: i32 = 20; // per enemy bullet cleared by the graze burst
 pub const ENERMY_SPAWN_STARTING_COUNT: i32 = 2;
 pub const TIME_UNTIL_MAX_DIFFICULTY:f32 = 70f32; 
 pub const ENERMY_SPAWN_MAX_COUNT: i32 = 9;
 pub const ENERMY_SPAWN_TIME: f32 = 0.5f32;
 
 
-pub const ENERMY_SPEED_HOMING: Vec2 = const_vec2!([0.2f32, 3f32]); 
+pub const ENERMY_HOMING_WIND_UP_TIME: f32 = 0.8f32;
+pub const ENERMY_HOMING_WIND_UP_RISE: f32 = 8f32;
+pub const ENERMY_HOMING_MAX_SPEED: f32 = 70f32;
+pub const ENERMY_HOMING_ACCELERATION: f32 = 90f32;
+pub const ENERMY_HOMING_TURN_RATE: f32 = 2.5f32; // radians per second
+pub const ENERMY_HOMING_ARRIVAL_RADIUS: f32 = 30f32;
+pub const ENERMY_HOMING_ARRIVAL_MIN_FRACTION: f32 = 0.6f32; // never slows below this much of the max speed
+pub const ENERMY_KAMIKAZE_LOCK_TIME: f32 = 0.9f32; // tracks the player this long before dashing
+pub const ENERMY_KAMIKAZE_DASH_SPEED: f32 = 160f32;
+pub const ENERMY_KAMIKAZE_OFF_SCREEN_MARGIN: f32 = 16f32;
+pub const ENERMY_SNIPER_AIM_TIME: f32 = 1.4f32;
+pub const ENERMY_SNIPER_HOLD_TIME: f32 = 0.4f32; // the laser stops tracking and blinks this long before the shot
+pub const ENERMY_SNIPER_SHOT_SPEED: f32 = 220f32;
+pub const ENERMY_SHIELD_TURN_RATE: f32 = 1.2f32; // radians per second
+pub const ENERMY_SHIELD_RADIUS: f32 = 7f32;
+pub const ENERMY_ARCHETYPE_WEIGHTS: [(EnermyType, f32, i32); 4] = [ // type, spawn weight, first wave it shows up in
+    (EnermyType::NORMAL, 6f32, 0),
+    (EnermyType::Kamikaze, 1.5f32, 2),
+    (EnermyType::Shielded, 1f32, 3),
+    (EnermyType::Sniper, 1f32, 4),
+];
 pub const SCORE_SURVIVED_ALL: i32 = 750;
 pub const SCORE_KILL_ALL: i32 = 1000;
 pub const ENERMY_BULLET_SPEED: f32 = 80f32;
 pub const BULLET_ANIM_TIME_SPAWN: f32 = 0.3f32;
 pub const PLAYER_BULLET_SPEED:f32 = 80f32; 
 pub const PLAYER_LIVES_MAX:i32 = 7i32; 
-pub const ENERMY_SHOOT_BURST_TIME: f32 = 0.2f32;
+pub const SCORE_EXTRA_LIFE_THRESHOLDS: [i32; 3] = [5000, 15000, 30000];
+pub const SCORE_EXTRA_LIFE_EVERY: i32 = 25000; // after the last threshold
+pub const CONTINUES_MAX: i32 = 3;
+pub const CONTINUE_TIME: f32 = 9f32;
+pub const CONTINUE_SCORE_KEEP_FRACTION: f32 = 0f32; // 0 resets the score on continue, 0.5 halves it
 pub const SCORE_MINI:i32 = 20; 
 pub const SCORE_NORMAL:i32 = 100; 
-pub const ENERMY_SHOOT_TIME: f32 = 2f32;
-pub const ENERMY_MAX_BURST_COUNT: i32 = 5;
+pub const SCORE_KAMIKAZE: i32 = 150;
+pub const SCORE_SHIELDED: i32 = 200;
+pub const SCORE_SNIPER: i32 = 175;
+pub const ENERMY_AIM_LEAD: f32 = 0.5f32; // 0 shoots where the player is, 1 where they will be
+pub const ENERMY_AIM_SPREAD: f32 = 8f32; // degrees
+pub const ENERMY_HEALTH_WAVES_PER_HIT: i32 = 3; // normal demons take one more hit every n waves
+pub const ENERMY_HEALTH_MAX: i32 = 4;
+pub const ENERMY_HIT_FLASH_TIME: f32 = 0.08f32;
+pub const ENERMY_KNOCKBACK_SPEED: f32 = 40f32;
+pub const ENERMY_KNOCKBACK_DAMPING: f32 = 12f32;
+pub const ENERMY_SEPARATION_RADIUS: f32 = 10f32; // demons closer than this push each other apart
+pub const ENERMY_SEPARATION_SPEED: f32 = 40f32;
+pub const ENERMY_CHILDREN_FAN_OUT_SPEED: f32 = 50f32; // minis burst outwards when their parent dies
+pub const ENERMY_CHILDREN_FAN_OUT_ANGLE: f32 = 120f32; // degrees, centered on straight up
+pub const ENERMY_DEATH_TIME: f32 = 0.45f32;
+pub const ENERMY_DEATH_PARTICLES: i32 = 10;
+pub const ENERMY_DEATH_PARTICLE_SPEED: f32 = 60f32;
+pub const ENERMY_DEATH_PARTICLE_DAMPING: f32 = 5f32;
+pub const ENERMY_DAMAGED_TINT: Color = Color::new(0.65f32, 0.5f32, 0.5f32, 1f32);
+pub const HAZARD_FIRST_WAVE: i32 = 2;
+pub const HAZARD_EVENT_TIME_RANGE: Vec2 = const_vec2!([18f32, 32f32]); // seconds between events
+pub const HAZARD_WARNING_TIME: f32 = 1.5f32;
+pub const HAZARD_METEOR_TIME: f32 = 3.5f32;
+pub const HAZARD_METEOR_SPAWN_TIME: f32 = 0.18f32;
+pub const HAZARD_METEOR_SPEED: f32 = 110f32;
+pub const HAZARD_METEOR_RADIUS: f32 = 2f32;
+pub const HAZARD_METEOR_SLANT: f32 = 0.35f32; // sideways px per px fallen
+pub const HAZARD_DEBRIS_COUNT: i32 = 5;
+pub const HAZARD_DEBRIS_RADIUS: f32 = 3f32;
+pub const HAZARD_DEBRIS_SPEED: f32 = 10f32;
+pub const HAZARD_DEBRIS_GRAVITY: f32 = 90f32;
+pub const HAZARD_LASER_SPEED: f32 = 45f32;
+pub const HAZARD_LASER_WIDTH: f32 = 4f32;
+pub const SCORE_BULLET_SHOT_DOWN: i32 = 15; // per orb shot down
+pub const BULLET_ORB_TINT: Color = Color::new(1f32, 0.6f32, 0.3f32, 1f32);
+pub const SCORE_HAZARD_KILL: i32 = 50; // demons caught by a hazard
+pub const SPATIAL_HASH_CELL_SIZE: f32 = 16f32;
+pub const WORLD_ENTITY_CAPACITY: usize = 256; // grows past this if it has to
+pub const BULLET_CULL_MARGIN: f32 = 24f32;
+pub const BULLET_LIFETIME: f32 = 15f32; // slow curling bullets can't hang around forever
+pub const DEBUG_FONT_SIZE: u16 = 6;
+pub const BUNKER_COUNT: i32 = 4;
+pub const BUNKER_PLAYER_GAP: f32 = 10f32; // space between the bottom of the bunkers and the top of the player
+pub const BUNKER_COLOR: Color = Color::new(0.45f32, 0.75f32, 0.4f32, 1f32);
+pub const BUNKER_CHIP_RADIUS: f32 = 2.5f32;
+pub const BUNKER_CHIP_RADIUS_CONTACT: f32 = 4.5f32; // enemies crashing in take a bigger bite
+pub const FORMATION_WAVE_INTERVAL: i32 = 2; // every n-th wave flies in formation
+pub const FORMATION_COLUMNS: i32 = 8;
+pub const FORMATION_ROWS: i32 = 3;
+pub const FORMATION_SPACING: Vec2 = const_vec2!([22f32, 14f32]);
+pub const FORMATION_TOP: f32 = 10f32;
+pub const FORMATION_SWAY_X: f32 = 16f32;
+pub const FORMATION_SWAY_SPEED: f32 = 0.8f32;
+pub const FORMATION_ENTER_TIME: f32 = 2f32;
+pub const FORMATION_DIVE_TIME: f32 = 2.5f32;
+pub const FORMATION_DIVE_INTERVAL: f32 = 2.5f32;
+pub const FORMATION_DIVE_SHOOT_DELAY: f32 = 0.6f32;
+pub const BOSS_WAVE_INTERVAL: i32 = 5; // a boss shows up every n waves
+pub const BOSS_HEALTH: i32 = 60;
+pub const BOSS_HEALTH_PER_APPEARANCE: i32 = 20; // every boss after the first is tougher
+pub const BOSS_SCALE: f32 = 4f32;
+pub const BOSS_HOVER_Y: f32 = 12f32;
+pub const BOSS_FOLLOW_SPEED: f32 = 3f32;
+pub const BOSS_ANIM_TIME_SPAWN: f32 = 1.5f32;
+pub const BOSS_PHASE_CHANGE_PAUSE: f32 = 1f32;
+pub const BOSS_WARNING_TIME: f32 = 3f32;
+pub const BOSS_WARNING_SOUND_TIME: f32 = 0.6f32;
+pub const SCORE_BOSS: i32 = 5000;
+pub const COMBO_CHAIN_TIME: f32 = 1.5f32; // max time between kills to keep a chain going
+pub const COMBO_KILLS_PER_MULTIPLIER: i32 = 5;
+pub const COMBO_MULTIPLIER_MAX: i32 = 8;
+pub const COMBO_MISS_PENALTY: i32 = 2;
+pub const SCORE_BROOD_CLEARED: i32 = 150; // killing every mini spawned by the same demon
+pub const FLOATING_TEXT_LIFETIME: f32 = 0.8f32;
+pub const FLOATING_TEXT_SPEED: f32 = 30f32;
+pub const FLOATING_TEXT_DAMPING: f32 = 3f32;
+pub const FLOATING_TEXT_FONT_SIZE: u16 = 6;
+pub const FLOATING_TEXT_STACK_TIME: f32 = 0.2f32;
 
 //** KEY-MOVEMENT */
 pub const KEY_RIGHT: KeyCode = KeyCode::Right;
 pub const KEY_LEFT: KeyCode = KeyCode::Left;
 pub const KEY_SHOOT: KeyCode = KeyCode::Space;
+pub const KEY_SPECIAL: KeyCode = KeyCode::X;
 pub const KEY_START_GAME: KeyCode = KeyCode::Space; 
+pub const KEY_START_VERSUS: KeyCode = KeyCode::V;
+pub const KEY_CONTINUE: KeyCode = KeyCode::Enter;
+pub const KEY_DEBUG_PATHS: KeyCode = KeyCode::F1;
+pub const KEY_DEBUG_POOL: KeyCode = KeyCode::F2;
+
+//** VERSUS */
+pub const KEY_VERSUS_P1_LEFT: KeyCode = KeyCode::A;
+pub const KEY_VERSUS_P1_RIGHT: KeyCode = KeyCode::D;
+pub const KEY_VERSUS_P1_SHOOT: KeyCode = KeyCode::W;
+pub const KEY_VERSUS_P1_SPECIAL: KeyCode = KeyCode::S;
+pub const KEY_VERSUS_P2_LEFT: KeyCode = KeyCode::Left;
+pub const KEY_VERSUS_P2_RIGHT: KeyCode = KeyCode::Right;
+pub const KEY_VERSUS_P2_SHOOT: KeyCode = KeyCode::Up;
+pub const KEY_VERSUS_P2_SPECIAL: KeyCode = KeyCode::Down;
+pub const VERSUS_ATTACK_WAVE_CLEARED: i32 = 2; // enemies sent to the opponent for clearing a wave
+pub const VERSUS_CHAIN_KILLS_PER_ATTACK: i32 = 3; 
+pub const VERSUS_ATTACK_SPAWN_TIME: f32 = 0.4f32; 
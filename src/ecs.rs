@@ -0,0 +1,385 @@
+use macroquad::prelude::*;
+
+use crate::{
+    bullet::{BulletHurtType, BulletKind, BulletMotion},
+    constants::*,
+    enermy::Enermy,
+    player::Player,
+    sweep
+};
+
+
+// an id for one game object, goes stale once it is despawned even if its slot gets reused
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entity {
+    index: usize,
+    generation: u32
+}
+
+
+// hands out entity ids, slots get reused so spawning doesn't allocate once the world is warm
+pub struct Entities {
+    generations: Vec<u32>, // bumped every time the slot is freed
+    alive: Vec<bool>,
+    free: Vec<usize> // empty slots, the last one is handed out next
+}
+
+
+impl Entities {
+    fn new(capacity: usize) -> Self {
+        Entities {
+            generations: vec![0; capacity],
+            alive: vec![false; capacity],
+            free: (0..capacity).rev().collect()
+        }
+    }
+
+    // only grows when every slot is taken
+    fn alloc(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.generations.len() - 1
+            }
+        };
+        self.alive[index] = true;
+        Entity { index, generation: self.generations[index] }
+    }
+
+    fn free(&mut self, entity: Entity) {
+        self.alive[entity.index] = false;
+        self.generations[entity.index] += 1;
+        self.free.push(entity.index);
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive[entity.index] && self.generations[entity.index] == entity.generation
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(index, _)| Entity { index, generation: self.generations[index] })
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.generations.len() - self.free.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.generations.len()
+    }
+}
+
+
+// one component type, stored by entity slot
+pub struct Storage<T> {
+    items: Vec<Option<(u32, T)>> // generation of the owner next to the component
+}
+
+
+impl<T> Storage<T> {
+    fn new(capacity: usize) -> Self {
+        Storage {
+            items: (0..capacity).map(|_| None).collect()
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, component: T) {
+        if entity.index >= self.items.len() {
+            self.items.resize_with(entity.index + 1, || None);
+        }
+        self.items[entity.index] = Some((entity.generation, component));
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(item) = self.items.get_mut(entity.index) {
+            *item = None;
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.items.get(entity.index) {
+            Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.items.get_mut(entity.index) {
+            Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+            _ => None
+        }
+    }
+
+    // moves the component out, so it can be updated against the rest of the world, put it back afterwards
+    pub fn take(&mut self, entity: Entity) -> Option<T> {
+        self.get(entity)?;
+        self.items[entity.index].take().map(|(_, component)| component)
+    }
+
+    pub fn put(&mut self, entity: Entity, component: T) {
+        self.insert(entity, component);
+    }
+}
+
+
+pub struct Transform {
+    pub pos: Vec2,
+    pub rotation: f32
+}
+
+
+// a strip of frames side by side, turned to face the way the entity moves
+pub struct Sprite {
+    pub texture: Texture2D,
+    pub frame_count: f32,
+    pub tint: Color,
+    pub forward: Vec2 // the way the texture points when not rotated
+}
+
+
+// time since the spawn, drives the frames and the lifetime, looping ones wrap around instead
+pub struct Animation {
+    pub timer: f32,
+    pub loop_time_optional: Option<f32>
+}
+
+
+pub struct Collider {
+    pub frame_size: Vec2, // unrotated size, the rect turns on its side for sideways movers
+    pub offset: Vec2, // from the transform to the rect, for entities without a motion
+    pub rect: Rect,
+    pub prev_rect: Rect // where the last update started, hits are traced from here
+}
+
+
+impl Collider {
+    // sits still at offset from pos, the whole move of the first update is traced from there
+    pub fn at(pos: Vec2, offset: Vec2, frame_size: Vec2) -> Self {
+        let rect = Rect::new(pos.x + offset.x, pos.y + offset.y, frame_size.x, frame_size.y);
+        Collider { frame_size, offset, rect, prev_rect: rect }
+    }
+
+    // where the rect of an entity without a motion goes once it stands at pos
+    pub fn rect_at(&self, pos: Vec2) -> Rect {
+        Rect::new(pos.x + self.offset.x, pos.y + self.offset.y, self.frame_size.x, self.frame_size.y)
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.rect.point() + self.rect.size() * 0.5f32
+    }
+
+    fn sweep_delta(&self) -> Vec2 {
+        self.rect.point() - self.prev_rect.point()
+    }
+
+    // all the ground covered this update, for the broadphase
+    pub fn swept_rect(&self) -> Rect {
+        sweep::bounds(&self.prev_rect, self.sweep_delta())
+    }
+
+    // the rect at every pixel of this update's move, so a dt spike can't jump over anything thin
+    pub fn swept_rects(&self) -> impl Iterator<Item = Rect> {
+        sweep::path(self.prev_rect, self.sweep_delta(), 0f32)
+    }
+
+    // both colliders moved this update, so trace this one against the other as if it stood still
+    pub fn sweep_hits_collider(&self, other: &Collider) -> bool {
+        let relative = self.sweep_delta() - other.sweep_delta();
+        sweep::time_of_impact(&self.prev_rect, relative, &other.prev_rect).is_some()
+    }
+
+    // traced from where the move first touches target, overlaps does the exact test on the way
+    pub fn sweep_hits(&self, target: &Rect, overlaps: impl Fn(&Rect) -> bool) -> bool {
+        let delta = self.sweep_delta();
+        match sweep::time_of_impact(&self.prev_rect, delta, target) {
+            Some(start) => sweep::path(self.prev_rect, delta, start).any(|rect| overlaps(&rect)),
+            None => false
+        }
+    }
+}
+
+
+// what makes an entity a bullet, and who it hurts
+pub struct Projectile {
+    pub hurt_type: BulletHurtType,
+    pub kind: BulletKind,
+    pub is_kill: bool, // hit something, gets despawned at the end of the frame
+    pub grazed: bool
+}
+
+
+// every game object, where it is, how it animates and what it collides with sit in the shared storages,
+// players and enermies only keep the game state the systems don't know about
+pub struct World {
+    pub entities: Entities,
+    pub transforms: Storage<Transform>,
+    pub bullet_motions: Storage<BulletMotion>,
+    pub sprites: Storage<Sprite>,
+    pub animations: Storage<Animation>,
+    pub colliders: Storage<Collider>,
+    pub projectiles: Storage<Projectile>,
+    pub players: Storage<Player>,
+    pub enermies: Storage<Enermy>,
+    enermy_order: Vec<Entity> // spawn order, demons update in it so slot reuse can't change how a game plays
+}
+
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            entities: Entities::new(WORLD_ENTITY_CAPACITY),
+            transforms: Storage::new(WORLD_ENTITY_CAPACITY),
+            bullet_motions: Storage::new(WORLD_ENTITY_CAPACITY),
+            sprites: Storage::new(WORLD_ENTITY_CAPACITY),
+            animations: Storage::new(WORLD_ENTITY_CAPACITY),
+            colliders: Storage::new(WORLD_ENTITY_CAPACITY),
+            projectiles: Storage::new(WORLD_ENTITY_CAPACITY),
+            players: Storage::new(1),
+            enermies: Storage::new(WORLD_ENTITY_CAPACITY),
+            enermy_order: Vec::new()
+        }
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.alloc()
+    }
+
+    pub fn insert_transform(&mut self, entity: Entity, transform: Transform) {
+        self.transforms.insert(entity, transform);
+    }
+
+    pub fn insert_bullet_motion(&mut self, entity: Entity, motion: BulletMotion) {
+        self.bullet_motions.insert(entity, motion);
+    }
+
+    pub fn insert_sprite(&mut self, entity: Entity, sprite: Sprite) {
+        self.sprites.insert(entity, sprite);
+    }
+
+    pub fn insert_animation(&mut self, entity: Entity, animation: Animation) {
+        self.animations.insert(entity, animation);
+    }
+
+    pub fn insert_collider(&mut self, entity: Entity, collider: Collider) {
+        self.colliders.insert(entity, collider);
+    }
+
+    pub fn insert_projectile(&mut self, entity: Entity, projectile: Projectile) {
+        self.projectiles.insert(entity, projectile);
+    }
+
+    pub fn insert_player(&mut self, entity: Entity, player: Player) {
+        self.players.insert(entity, player);
+    }
+
+    pub fn insert_enermy(&mut self, entity: Entity, enermy: Enermy) {
+        self.enermies.insert(entity, enermy);
+        self.enermy_order.push(entity);
+    }
+
+    // in spawn order
+    pub fn enermy_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.enermy_order.iter().copied()
+    }
+
+    pub fn enermy_count(&self) -> usize {
+        self.enermy_order.len()
+    }
+
+    // the index-th in spawn order, for going through them while the world gets changed
+    pub fn enermy_at(&self, index: usize) -> Entity {
+        self.enermy_order[index]
+    }
+
+    pub fn enermies(&self) -> impl Iterator<Item = &Enermy> + '_ {
+        self.enermy_order.iter().filter_map(move |entity| self.enermies.get(*entity))
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.entities.is_alive(entity) {
+            return;
+        }
+        self.transforms.remove(entity);
+        self.bullet_motions.remove(entity);
+        self.sprites.remove(entity);
+        self.animations.remove(entity);
+        self.colliders.remove(entity);
+        self.projectiles.remove(entity);
+        self.players.remove(entity);
+        if self.enermies.get(entity).is_some() {
+            self.enermies.remove(entity);
+            self.enermy_order.retain(|enermy| *enermy != entity);
+        }
+        self.entities.free(entity);
+    }
+
+    pub fn clear(&mut self) {
+        let entities: Vec<Entity> = self.entities.iter().collect();
+        for entity in entities {
+            self.despawn(entity);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a bullet that moved by delta this update, ending at end
+    fn moved_collider(end: Rect, delta: Vec2) -> Collider {
+        Collider {
+            frame_size: end.size(),
+            offset: vec2(0f32, 0f32),
+            rect: end,
+            prev_rect: Rect::new(end.x - delta.x, end.y - delta.y, end.w, end.h)
+        }
+    }
+
+    #[test]
+    fn bullet_swept_rects_catch_a_thin_target() {
+        // 400px/s at dt = 0.5, jumps clean over the line with end-of-frame rects
+        let bullet = moved_collider(Rect::new(10f32, 200f32, 3f32, 5f32), vec2(0f32, 200f32));
+        let target = Rect::new(0f32, 100f32, 40f32, 1f32);
+        assert!(!bullet.rect.overlaps(&target) && !bullet.prev_rect.overlaps(&target));
+        assert!(bullet.swept_rect().overlaps(&target));
+        assert!(bullet.swept_rects().any(|rect| rect.overlaps(&target)));
+        assert!(bullet.sweep_hits(&target, |rect| rect.overlaps(&target)));
+
+        let beside = Rect::new(20f32, 100f32, 20f32, 1f32);
+        assert!(!bullet.swept_rects().any(|rect| rect.overlaps(&beside)));
+        assert!(!bullet.sweep_hits(&beside, |rect| rect.overlaps(&beside)));
+    }
+
+    #[test]
+    fn bullets_crossing_head_on_hit() {
+        let shot = moved_collider(Rect::new(10f32, 20f32, 2f32, 4f32), vec2(0f32, -60f32));
+        let orb = moved_collider(Rect::new(10f32, 70f32, 4f32, 4f32), vec2(0f32, 60f32));
+        assert!(!shot.rect.overlaps(&orb.rect) && !shot.prev_rect.overlaps(&orb.prev_rect));
+        assert!(shot.sweep_hits_collider(&orb));
+        assert!(orb.sweep_hits_collider(&shot));
+
+        let wide = moved_collider(Rect::new(30f32, 70f32, 4f32, 4f32), vec2(0f32, 60f32));
+        assert!(!shot.sweep_hits_collider(&wide));
+    }
+
+    #[test]
+    fn taken_components_only_go_back_to_their_own_entity() {
+        let mut world = World::new();
+        let stale = world.spawn();
+        world.despawn(stale);
+        let entity = world.spawn(); // same slot, newer generation
+
+        let mut storage = Storage::new(1);
+        storage.insert(entity, 7);
+        assert!(storage.take(stale).is_none());
+        let taken = storage.take(entity).unwrap();
+        assert!(storage.get(entity).is_none());
+        storage.put(entity, taken + 1);
+        assert_eq!(storage.get(entity), Some(&8));
+    }
+}
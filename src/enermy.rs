@@ -1,23 +1,50 @@
 
 use macroquad::prelude::*;
-use quad_snd::mixer::{SoundMixer, Volume}; 
+use quad_snd::mixer::Volume; 
 use rand::rand; 
 
 
 
 use crate::{
     constants::*, 
-    resources::{Resources, SoundIdentifier},
+    resources::{Resources, SoundIdentifier, SoundQueue, DemonSheet},
     wave::{ WaveManager, LastEnermyDeathReason},
-    bullet:: { Bullet, BulletHurtType }
+    bullet:: { self, BulletHurtType, BulletKind, BulletMotion },
+    ecs::{Animation, Collider, Transform, World},
+    boss::{ self, BOSS_PHASES },
+    bullet_pattern::{PatternAim, PatternRunner},
+    behaviour::{ ActionState, BehaviourAction, BehaviourActor, BehaviourRunner, BehaviourStatus },
+    enermy_profile::EnermyProfile,
+    formation::Formation,
+    spline::{ self, PathFollower },
+    sprite_mask::SpriteMask
 };
+use std::rc::Rc; 
 
 
 
 #[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)] // NORMAL and MINI are older than the rest
 pub enum EnermyType {
     NORMAL, 
-    MINI
+    MINI, 
+    Boss, 
+    Kamikaze, 
+    Shielded, 
+    Sniper
+}
+
+
+impl EnermyType {
+    // the archetypes have a profile of their own, the rest go by color
+    pub fn profile_name(&self, enermy_color: EnermyColor) -> &'static str {
+        match self {
+            EnermyType::Kamikaze => "kamikaze", 
+            EnermyType::Shielded => "shielded", 
+            EnermyType::Sniper => "sniper", 
+            EnermyType::NORMAL | EnermyType::MINI | EnermyType::Boss => enermy_color.name()
+        }
+    }
 }
 
 
@@ -40,52 +67,115 @@ impl EnermyColor {
         let all = [PURPLE, GREEN, RED];
         return all[rand::gen_range(0, all.len())]
     }
+
+    // explosion and particle color
+    pub fn tint(&self) -> Color {
+        match self {
+            EnermyColor::PURPLE => Color::new(0.8f32, 0.5f32, 1f32, 1f32), 
+            EnermyColor::RED => Color::new(1f32, 0.45f32, 0.35f32, 1f32), 
+            EnermyColor::GREEN => Color::new(0.5f32, 1f32, 0.45f32, 1f32)
+        }
+    }
+
+    // matches the profile names in assets/profiles/demons.profile
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnermyColor::PURPLE => "purple", 
+            EnermyColor::RED => "red", 
+            EnermyColor::GREEN => "green"
+        }
+    }
 }
 
 
 pub struct EnermyStateShared {
     texture: Texture2D, 
-    angle:f32, 
-    angle_speed: f32, 
-    collision_rect: Rect, 
     pub health: i32, 
+    pub max_health: i32, 
     pub death_method: EnermyDeathMethod, 
-    animation_timer: f32, 
     pub enermy_type: EnermyType, 
     pub enermy_color: EnermyColor, 
-    pub pos: Vec2, 
-    charge_timer_optional: Option<f32>, // // used for mini enemies, that home in on player
+    pub brood_id_optional: Option<u32>, // minis spawned from the same parent share an id
+    pub killed_by_player: bool, 
+    pub pattern_optional: Option<String>, // bullet pattern used when shooting, see Resources::bullet_patterns
+    pub profile: Rc<EnermyProfile>, 
+    pub path_optional: Option<PathFollower>, // the path normal state movement follows, None holds still
+    pub armor_hits: i32, 
+    pub armor_facing: Vec2, // straight down, shielded demons turn it towards the player
+    aim_line_optional: Option<(Vec2, bool)>, // point a telegraph laser aims at, and whether it stopped tracking
+    mask_optional: Option<Rc<SpriteMask>>, // pixels the sprite covers, None sticks to the collision rect
+    hit_flash_timer: f32, 
+    knockback_vel: Vec2, 
 }
 
 
 pub enum EnermyState {
-    Homing(EnermyStateHoming), 
-    Normal(EnermyStateNormal), 
     Spawning(EnermyStateSpawning), 
-    Shooting(EnermyStateShooting)
+    Behaviour(EnermyStateBehaviour), // normal demons and minis, moving and shooting as their behaviour tree says
+    Boss(EnermyStateBoss), 
+    Entering(EnermyStateEntering), 
+    InFormation(EnermyStateInFormation), 
+    Diving(EnermyStateDiving), 
+    Dying(EnermyStateDying) // exploding, no longer collides and gets removed once the animation is done
 }
 
 
-#[derive(PartialEq)]
-pub struct EnermyStateNormal {
-    shoot_timer: f32
+pub struct EnermyStateBehaviour {
+    runner: BehaviourRunner
 }
 
 
 #[derive(PartialEq)]
-pub struct EnermyStateShooting {
-    shots_left: i32,
-    shoot_timer: f32,
+pub struct EnermyStateSpawning {
+    spawn_timer: f32,
 }
 
 
-#[derive(PartialEq)]
-pub struct EnermyStateHoming {}
+pub struct EnermyStateBoss {
+    phase: usize, 
+    move_timer: f32, 
+    shoot_timer: f32, 
+    runner_optional: Option<PatternRunner>
+}
 
 
-#[derive(PartialEq)]
-pub struct EnermyStateSpawning {
-    spawn_timer: f32,
+
+// flying along a curve into a formation slot, the end of the curve follows the slot as it sways
+pub struct EnermyStateEntering {
+    slot: usize, 
+    start: Vec2, 
+    controls: [Vec2; 2], 
+    timer: f32
+}
+
+
+pub struct EnermyStateInFormation {
+    slot: usize
+}
+
+
+// keeps the slot so it can fly back in after the run
+pub struct EnermyStateDiving {
+    slot: usize, 
+    start: Vec2, 
+    turn_dir: f32, 
+    timer: f32, 
+    has_shot: bool, 
+    runner_optional: Option<PatternRunner>
+}
+
+
+
+pub struct EnermyStateDying {
+    timer: f32, 
+    center: Vec2, // where it blew up, the explosion stays put while the rect drifts with the knockback
+    particles: Vec<DeathParticle>
+}
+
+
+pub struct DeathParticle {
+    pos: Vec2, 
+    vel: Vec2
 }
 
 
@@ -107,75 +197,92 @@ pub enum EnermyDeathMethod {
 
 impl Enermy {
     pub fn new(
-        pos: Vec2, 
-        texture: Texture2D, 
+        sheet: DemonSheet, 
         health: i32, 
         death_method: EnermyDeathMethod, 
         enermy_type: EnermyType, 
-        enermy_color: EnermyColor
+        enermy_color: EnermyColor, 
+        profile: Rc<EnermyProfile>
     ) -> Self {
-        let charge_timer_optional = match enermy_type {
-            EnermyType::NORMAL => None, 
-            EnermyType::MINI => Some(rand::gen_range(
-                ENERMY_MINI_HOMING_TIME_RANGE.x,
-                ENERMY_MINI_HOMING_TIME_RANGE.y
-            )),
+        let pattern_optional = match enermy_type {
+            EnermyType::MINI | EnermyType::Boss => None, 
+            _ => profile.pick_pattern()
         }; 
 
         Enermy {
             state_shared: EnermyStateShared {
-                pos, 
-                texture, 
+                texture: sheet.texture, 
                 health, 
-                angle: 0f32, 
+                max_health: health, 
                 death_method, 
-                animation_timer: 0f32,
                 enermy_color, 
                 enermy_type,
-                angle_speed: rand::gen_range(100 as f32, 50 as f32), 
-                collision_rect: Rect::new(0f32, 0f32, texture.width(), texture.height()), 
-                charge_timer_optional,
+                brood_id_optional: None, 
+                killed_by_player: false,
+                pattern_optional,
+                path_optional: None,
+                armor_hits: profile.armor_hits,
+                armor_facing: vec2(0f32, 1f32),
+                aim_line_optional: None,
+                mask_optional: sheet.mask_optional,
+                hit_flash_timer: 0f32,
+                knockback_vel: vec2(0f32, 0f32),
+                profile,
             }, 
             state: EnermyState::Spawning(EnermyStateSpawning { spawn_timer: 0f32})
         }
 
     }
 
-    pub fn update(
-        &mut self, 
-        dt: f32, 
-        bullets: &mut Vec<Bullet>, 
-        resources: &Resources, 
-        player_pos: &Vec2, 
-        game_manager: &mut WaveManager, 
-        sound_mixer: &mut SoundMixer
-    ) {
+    // one frame of the sprite, the boss covers its scaled up size and is drawn centred on pos
+    pub fn collider_at(&self, pos: Vec2) -> Collider {
+        let texture = &self.state_shared.texture; 
+        match self.state_shared.enermy_type {
+            EnermyType::Boss => {
+                let size = Self::boss_size(texture); 
+                Collider::at(pos, vec2(-size.x * 0.5f32, 0f32), size)
+            }
+            _ => Collider::at(pos, vec2(0f32, 0f32), vec2(texture.width() / ENERMY_SPRITE_FRAMES as f32, texture.height()))
+        }
+    }
+
+    // collider still holds the rect of the last frame, the colliders system moves it after all demons updated
+    pub fn update(&mut self, transform: &mut Transform, collider: &Collider, context: &mut EnermyContext) {
+        let dt = context.dt; 
+        let pos = &mut transform.pos; 
+        Self::update_hit_effects(&mut self.state_shared, pos, dt); 
+        if let EnermyType::Shielded = self.state_shared.enermy_type {
+            Self::turn_shield(&mut self.state_shared, *pos, &context.player_pos, dt); 
+        }
+        if !self.is_alive() && !matches!(self.state, EnermyState::Dying(_)) {
+            self.state = EnermyState::Dying(Self::new_state_dying(&self.state_shared, collider)); 
+        }
 
         let command_optional = match &mut self.state {
             EnermyState::Spawning(state_data) => {
-                Self::update_state_spawning(&mut self.state_shared, dt, state_data)
-            }
-            EnermyState::Normal(state_data) => {
-                Self::update_state_normal(&mut self.state_shared, dt, state_data)
+                Self::update_state_spawning(&mut self.state_shared, dt, state_data, context.resources)
             }
 
-            EnermyState::Shooting(state_data) => Self::update_state_shooting(
-                &mut self.state_shared,
-                dt, 
-                bullets,
-                resources, 
-                state_data, 
-                sound_mixer
-            ),
+            EnermyState::Behaviour(state_data) => Self::update_state_behaviour(&mut self.state_shared, pos, collider, state_data, context),
 
-            EnermyState::Homing(state_data) => Self::update_state_homing(
-                &mut self.state_shared,
+            EnermyState::Boss(state_data) => Self::update_state_boss(&mut self.state_shared, pos, state_data, context), 
+
+            EnermyState::Entering(state_data) => Self::update_state_entering(
+                pos,
                 dt,
-                player_pos,
-                game_manager,
-                sound_mixer,
-                resources,
-            )
+                state_data,
+                &context.wave_manager.formation,
+            ),
+
+            EnermyState::InFormation(state_data) => Self::update_state_in_formation(
+                pos,
+                state_data,
+                &context.wave_manager.formation,
+            ),
+
+            EnermyState::Diving(state_data) => Self::update_state_diving(&mut self.state_shared, pos, state_data, context), 
+
+            EnermyState::Dying(state_data) => Self::update_state_dying(dt, state_data)
         };
 
         match command_optional {
@@ -188,7 +295,7 @@ impl Enermy {
         }
     }
 
-    pub fn draw_state_spawning_normal(state_shared: &EnermyStateShared, state_data: &EnermyStateSpawning) {
+    pub fn draw_state_spawning_normal(state_shared: &EnermyStateShared, pos: Vec2, state_data: &EnermyStateSpawning) {
         let rand_frame = rand::gen_range(0i32, 2i32);
         let fraction = 1.0f32 - state_data.spawn_timer / ENERMY_MINI_ANIM_TIME_SPAWN; 
         let offset = fraction * ENERMY_MINI_ANIM_TIME_SPAWN; 
@@ -198,8 +305,8 @@ impl Enermy {
         //left-wing
         draw_texture_ex(
             state_shared.texture,
-            state_shared.pos.x - ((state_shared.texture.width() / 3.0f32) * 1.0f32) - offset,
-            state_shared.pos.y,
+            pos.x - ((state_shared.texture.width() / 3.0f32) * 1.0f32) - offset,
+            pos.y,
             WHITE, 
             DrawTextureParams {
                 rotation: 0f32, 
@@ -218,8 +325,8 @@ impl Enermy {
         //right-wing
         draw_texture_ex(
             state_shared.texture,
-            state_shared.pos.x + offset,
-            state_shared.pos.y,
+            pos.x + offset,
+            pos.y,
             WHITE,
             DrawTextureParams {
                 rotation: 0f32,
@@ -236,7 +343,7 @@ impl Enermy {
         );
     }
 
-    pub fn draw_state_spawning_mini (state_shared: &EnermyStateShared, state_data: &EnermyStateSpawning) {
+    pub fn draw_state_spawning_mini (state_shared: &EnermyStateShared, pos: Vec2, state_data: &EnermyStateSpawning) {
         let rand_frame = rand::gen_range(0i32, 2i32);
         let fraction = state_data.spawn_timer / ENERMY_MINI_ANIM_TIME_SPAWN;
         let spirit_width = state_shared.texture.width() / 4f32; 
@@ -244,8 +351,8 @@ impl Enermy {
 
         draw_texture_ex(
             state_shared.texture, 
-            state_shared.pos.x - ((state_shared.texture.width() / 4.0f32) * 1.0f32), 
-            state_shared.pos.y,
+            pos.x - ((state_shared.texture.width() / 4.0f32) * 1.0f32), 
+            pos.y,
             WHITE, 
             DrawTextureParams {
                 rotation: fraction * std::f32::consts::PI, 
@@ -263,8 +370,8 @@ impl Enermy {
         //right-wing
         draw_texture_ex(
             state_shared.texture, 
-            state_shared.pos.x, 
-            state_shared.pos.y, 
+            pos.x, 
+            pos.y, 
             WHITE, 
             DrawTextureParams {
                 rotation: fraction * std::f32::consts::PI * 2f32, 
@@ -283,21 +390,57 @@ impl Enermy {
         )
     }
 
-    pub fn draw_state_spawning(state_shared: &EnermyStateShared, state_data: &EnermyStateSpawning) {
+    pub fn draw_state_spawning(state_shared: &EnermyStateShared, pos: Vec2, animation_timer: f32, state_data: &EnermyStateSpawning) {
         match state_shared.enermy_type {
-            EnermyType::NORMAL => Self::draw_state_spawning_normal(state_shared, state_data),
-            EnermyType::MINI => Self::draw_state_spawning_mini(state_shared, state_data)
+            EnermyType::NORMAL | EnermyType::Kamikaze | EnermyType::Shielded | EnermyType::Sniper => {
+                Self::draw_state_spawning_normal(state_shared, pos, state_data)
+            }
+            EnermyType::MINI => Self::draw_state_spawning_mini(state_shared, pos, state_data),
+            EnermyType::Boss => {
+                let fraction = (state_data.spawn_timer / BOSS_ANIM_TIME_SPAWN).min(1f32); 
+                Self::draw_state_boss(state_shared, pos, animation_timer, fraction)
+            }
         }
     }
 
-    fn draw_state_normal(&self) {
-        let rand_frame = (self.state_shared.animation_timer / ENERMY_ANIM_TIME_FLAP).floor(); 
+    pub fn boss_size(texture: &Texture2D) -> Vec2 {
+        vec2(texture.width() / 4f32, texture.height()) * BOSS_SCALE
+    }
+
+    // fraction grows the boss in from nothing while it spawns
+    fn draw_state_boss(state_shared: &EnermyStateShared, pos: Vec2, animation_timer: f32, fraction: f32) {
+        let frame = (animation_timer / ENERMY_ANIM_TIME_FLAP).floor(); 
+        let size = Self::boss_size(&state_shared.texture) * fraction; 
+        let mut color = Self::damage_tint(state_shared); 
+        color.a = fraction; 
+
+        draw_texture_ex(
+            state_shared.texture,
+            pos.x - size.x * 0.5f32,
+            pos.y,
+            color,
+            DrawTextureParams {
+                dest_size: Some(size),
+                source: Some(Rect::new(
+                    state_shared.texture.width() / 4f32 * frame,
+                    0f32,
+                    state_shared.texture.width() / 4f32,
+                    state_shared.texture.height(),
+                )),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn draw_state_normal(&self, pos: Vec2, animation_timer: f32) {
+        let rand_frame = (animation_timer / ENERMY_ANIM_TIME_FLAP).floor(); 
+        let color = Self::damage_tint(&self.state_shared); 
         //left-wing
         draw_texture_ex(
             self.state_shared.texture,
-            self.state_shared.pos.x, 
-            self.state_shared.pos.y, 
-            WHITE,
+            pos.x, 
+            pos.y, 
+            color,
             DrawTextureParams {
                 rotation: 0f32, 
                 source: Some(Rect::new(
@@ -313,9 +456,9 @@ impl Enermy {
         //right-wing 
         draw_texture_ex(
             self.state_shared.texture,
-            self.state_shared.pos.x, 
-            self.state_shared.pos.y, 
-            WHITE, 
+            pos.x, 
+            pos.y, 
+            color, 
             DrawTextureParams {
                 rotation: 0f32, 
                 flip_x: true, 
@@ -330,8 +473,165 @@ impl Enermy {
         )
     }
 
-    pub fn overlaps(&self, other_rect: &Rect) -> bool {
-        self.state_shared.collision_rect.overlaps(other_rect)
+    // swoops in from the side the slot is on
+    pub fn enter_formation(&mut self, transform: &mut Transform, slot: usize, formation: &Formation) {
+        let slot_pos = formation.slot_pos(slot); 
+        let side_x = if slot_pos.x < GAME_CENTER_X { 16f32 } else { GAME_SIZE_X as f32 - 16f32 }; 
+        let start = vec2(side_x, -16f32); 
+        transform.pos = start; 
+        self.state_shared.path_optional = None; 
+        self.state = EnermyState::Entering(EnermyStateEntering {
+            slot, 
+            start, 
+            controls: [
+                vec2(side_x, GAME_SIZE_Y as f32 * 0.7f32), 
+                vec2(GAME_SIZE_X as f32 - side_x, GAME_SIZE_Y as f32 * 0.7f32)
+            ], 
+            timer: 0f32
+        }); 
+    }
+
+    pub fn start_dive(&mut self, pos: Vec2) {
+        if let EnermyState::InFormation(state_data) = &self.state {
+            let turn_dir = if pos.x < GAME_CENTER_X { -1f32 } else { 1f32 }; 
+            self.state = EnermyState::Diving(EnermyStateDiving {
+                slot: state_data.slot, 
+                start: pos, 
+                turn_dir, 
+                timer: 0f32, 
+                has_shot: false, 
+                runner_optional: None
+            }); 
+        }
+    }
+
+    pub fn formation_slot_optional(&self) -> Option<usize> {
+        match &self.state {
+            EnermyState::Entering(state_data) => Some(state_data.slot), 
+            EnermyState::InFormation(state_data) => Some(state_data.slot), 
+            EnermyState::Diving(state_data) => Some(state_data.slot), 
+            _ => None
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.state_shared.health > 0
+    }
+
+    // health ran out this frame, it hasn't started exploding yet
+    pub fn just_died(&self) -> bool {
+        !self.is_alive() && !matches!(self.state, EnermyState::Dying(_))
+    }
+
+    // the explosion is over, safe to remove
+    pub fn is_dead(&self) -> bool {
+        match &self.state {
+            EnermyState::Dying(state_data) => state_data.timer >= Self::death_time(&self.state_shared), 
+            _ => false
+        }
+    }
+
+    // overlaps along the whole move of this update, so a dash can't skip past other_rect
+    pub fn sweep_overlaps(&self, collider: &Collider, animation: &Animation, other_rect: &Rect) -> bool {
+        collider.sweep_hits(other_rect, |rect| {
+            // shift other_rect back instead of the demon, so the mask is still read at the current spot
+            let back = collider.rect.point() - rect.point(); 
+            self.overlaps(collider, animation, &Rect::new(other_rect.x + back.x, other_rect.y + back.y, other_rect.w, other_rect.h))
+        })
+    }
+
+    pub fn is_separated(&self) -> bool {
+        self.is_alive() 
+            && !matches!(self.state_shared.enermy_type, EnermyType::Boss)
+            && matches!(self.state, EnermyState::Spawning(_) | EnermyState::Behaviour(_))
+    }
+
+    // moves the demon and the path it follows, so the path doesn't pull it straight back
+    pub fn nudge(&mut self, transform: &mut Transform, offset: Vec2) {
+        transform.pos += offset; 
+        if let Some(path) = &mut self.state_shared.path_optional {
+            path.origin += offset; 
+        }
+    }
+
+    pub fn push(&mut self, vel: Vec2) {
+        self.state_shared.knockback_vel += vel; 
+    }
+
+    pub fn is_in_formation(&self) -> bool {
+        matches!(self.state, EnermyState::InFormation(_))
+    }
+
+    // homing minis, dashing kamikazes and dive-bombers take a life when they ram the player
+    pub fn hurts_on_contact(&self) -> bool {
+        match &self.state {
+            EnermyState::Behaviour(state_data) => match state_data.runner.running_action_state() {
+                Some((BehaviourAction::Home, _)) => true, 
+                // still locking on, only the dash itself hurts
+                Some((BehaviourAction::Dash, state)) => state.started && state.timer <= 0f32, 
+                _ => false
+            },
+            EnermyState::Diving(_) => true, 
+            _ => false
+        }
+    }
+
+    pub fn draw_path_debug(&self) {
+        if let Some(path) = &self.state_shared.path_optional {
+            let color = if path.is_finished() { GRAY } else { YELLOW }; 
+            path.draw_debug(color); 
+        }
+    }
+
+    // rect first, then only the pixels under other_rect if there is a mask
+    pub fn overlaps(&self, collider: &Collider, animation: &Animation, other_rect: &Rect) -> bool {
+        let rect = collider.rect; 
+        if !self.is_alive() || !rect.overlaps(other_rect) {
+            return false; 
+        }
+        let mask = match &self.state_shared.mask_optional {
+            Some(mask) => mask, 
+            None => return true
+        }; 
+
+        let frame = (animation.timer / ENERMY_ANIM_TIME_FLAP).floor() as usize; 
+        // the mask is in sheet pixels, the boss is drawn scaled up so other_rect gets scaled down to match
+        let scale = mask.frame_width as f32 / rect.w; 
+        let left = ((other_rect.x - rect.x) * scale).floor().max(0f32) as i32; 
+        let top = ((other_rect.y - rect.y) * scale).floor().max(0f32) as i32; 
+        let right = (((other_rect.x + other_rect.w - rect.x) * scale).ceil() as i32).min(mask.frame_width); 
+        let bottom = (((other_rect.y + other_rect.h - rect.y) * scale).ceil() as i32).min(mask.frame_height); 
+        let is_boss = matches!(self.state_shared.enermy_type, EnermyType::Boss); 
+        for y in top..bottom {
+            for x in left..right {
+                if mask.is_solid(frame, x, y) {
+                    return true; 
+                }
+                // the others draw both wings on the same spot, the right one is frame 1 mirrored
+                if !is_boss && mask.is_solid(1, mask.frame_width - 1 - x, y) {
+                    return true; 
+                }
+            }
+        }
+        false
+    }
+
+    // sidestep away from the closest player bullet flying up at us
+    fn dodge_dir(state_shared: &EnermyStateShared, pos: Vec2, collider: &Collider, world: &World) -> Option<f32> {
+        let profile = &state_shared.profile; 
+        if profile.dodge_speed <= 0f32 {
+            return None; 
+        }
+
+        let half_width = collider.rect.w * 0.5f32; 
+        let origin = vec2(collider.rect.x + half_width, pos.y); 
+        world.entities.iter()
+            .filter_map(|entity| Some((world.projectiles.get(entity)?, world.colliders.get(entity)?)))
+            .filter(|(projectile, _)| projectile.hurt_type == BulletHurtType::Enermy && !projectile.is_kill)
+            .map(|(_, collider)| collider.center() - origin)
+            .filter(|offset| offset.y > 0f32 && offset.y < profile.dodge_range && offset.x.abs() < half_width + 2f32)
+            .min_by(|a, b| a.y.partial_cmp(&b.y).unwrap())
+            .map(|offset| if offset.x > 0f32 { -1f32 } else { 1f32 })
     }
 
     pub fn clamp_in_view(pos: &mut Vec2) {
@@ -355,156 +655,803 @@ impl Enermy {
         state_shared: &mut EnermyStateShared,
         dt: f32,
         state_data: &mut EnermyStateSpawning,
+        resources: &Resources,
     )-> Option<EnermyCommand>{
         state_data.spawn_timer += dt;
 
         let end_time = match state_shared.enermy_type {
             EnermyType::MINI => ENERMY_MINI_ANIM_TIME_SPAWN, 
-            EnermyType::NORMAL => ENEMY_ANIM_TIME_SPAWN
+            EnermyType::Boss => BOSS_ANIM_TIME_SPAWN, 
+            _ => ENEMY_ANIM_TIME_SPAWN
         }; 
 
         let fraction = state_data.spawn_timer / end_time; 
         if fraction >= 1.0f32 {
-            return Some(EnermyCommand::ChangeState(EnermyState::Normal(EnermyStateNormal { shoot_timer : 0f32 })))
+            if let EnermyType::Boss = state_shared.enermy_type {
+                return Some(EnermyCommand::ChangeState(EnermyState::Boss(EnermyStateBoss {
+                    phase: 0, 
+                    move_timer: 0f32, 
+                    shoot_timer: BOSS_PHASE_CHANGE_PAUSE, 
+                    runner_optional: None
+                })))
+            }
+            let behaviour = match state_shared.enermy_type {
+                EnermyType::MINI => &state_shared.profile.mini_behaviour, 
+                _ => &state_shared.profile.behaviour
+            }; 
+            // profile behaviours are checked on startup
+            let runner = resources.enermy_behaviours.runner(behaviour).unwrap(); 
+            return Some(EnermyCommand::ChangeState(EnermyState::Behaviour(EnermyStateBehaviour { runner })))
         }
 
         None
     }
 
-    fn update_state_normal(
+    fn update_state_behaviour(
         state_shared: &mut EnermyStateShared,
+        pos: &mut Vec2,
+        collider: &Collider,
+        state_data: &mut EnermyStateBehaviour,
+        context: &mut EnermyContext,
+    ) -> Option<EnermyCommand> {
+        // a tree that ran out leaves the demon hovering where it is
+        if state_data.runner.is_finished() {
+            return None
+        }
+
+        let dt = context.dt; 
+        let mut actor = EnermyActor { state_shared, pos, collider, context }; 
+        state_data.runner.update(&mut actor, dt); 
+        None
+    }
+
+    fn update_wander(
+        state_shared: &mut EnermyStateShared,
+        pos: &mut Vec2,
+        collider: &Collider,
         dt: f32,
-        state_data: &mut EnermyStateNormal,
-    ) -> Option<EnermyCommand>{
-        let angle_change_speed = std::f32::consts::PI * state_shared.angle_speed; 
-        state_shared.angle += (get_time() as f32 * angle_change_speed).sin() * std::f32::consts::PI * 2f32 * dt;
-        let dir = vec2(state_shared.angle.sin(), -state_shared.angle.cos());
-        state_shared.pos.x += dt * ENERMY_SPEED * dt; 
+        world: &World,
+    ) {
+        let dodge_offset = match Self::dodge_dir(state_shared, *pos, collider, world) {
+            Some(dodge_dir) => dodge_dir * state_shared.profile.dodge_speed * dt, 
+            None => 0f32
+        }; 
 
+        // dodging moves the whole path along so the enemy doesn't snap back
+        match &mut state_shared.path_optional {
+            Some(path) => {
+                path.origin.x += dodge_offset; 
+                *pos = path.update(dt); 
+            }
+            None => pos.x += dodge_offset
+        }
 
         
-        Self::clamp_in_view(&mut state_shared.pos); 
-        state_shared.collision_rect.x = state_shared.pos.x - state_shared.texture.width() * 0.5f32; 
-        state_shared.collision_rect.y = state_shared.pos.y;
-        state_data.shoot_timer += dt; 
-
-
-        if let Some(charge_timer) = &mut state_shared.charge_timer_optional {
-            *charge_timer -= dt;
-            if *charge_timer <= 0f32  {
-                return Some(EnermyCommand::ChangeState(EnermyState::Homing(
-                    EnermyStateHoming {}, 
-                )))
-            }
+        Self::clamp_in_view(pos); 
+    }
+
+    // runs the pattern bursts_left times back to back, true once done
+    fn update_shooting(
+        state_shared: &mut EnermyStateShared,
+        pos: &mut Vec2,
+        runner: &mut PatternRunner,
+        bursts_left: &mut i32,
+        context: &mut EnermyContext,
+    ) -> bool {
+        let (dt, resources) = (context.dt, context.resources); 
+
+        let jitter_speed = state_shared.profile.speed * 0.5f32; 
+        pos.x += rand::gen_range(-1f32, 1f32) * jitter_speed * dt;
+        pos.y += rand::gen_range(-1f32, 1f32) * jitter_speed * dt;
+        Self::clamp_in_view(pos); 
+
+        let aim = context.aim_from(*pos); 
+        let bullets_fired = runner.update(dt, &aim, context.world, resources); 
+        if bullets_fired > 0 {
+            resources.play_sound(SoundIdentifier::EnermyShoot, context.sound_queue, Volume(1.0f32)); 
+            pos.y -= 2f32;
         }
 
-        state_shared.animation_timer += dt; 
 
-        if state_shared.animation_timer > ENERMY_ANIM_TIME_FLAP * 4f32 {
-            state_shared.animation_timer -= ENERMY_ANIM_TIME_FLAP * 4f32; 
+        if runner.is_finished() && *bursts_left > 1 {
+            *bursts_left -= 1; 
+            runner.restart(); 
         }
 
+        runner.is_finished()
+    }
 
-        if state_data.shoot_timer > ENERMY_SHOOT_TIME {
-            let shot_count = rand::gen_range(1, ENERMY_MAX_BURST_COUNT); 
-            
+    // wind_up_timer counts down before the charge, the demon backs up, blinks and screams meanwhile
+    fn update_homing(
+        state_shared: &mut EnermyStateShared,
+        pos: &mut Vec2,
+        collider: &Collider,
+        wind_up_timer: &mut f32,
+        vel: &mut Vec2,
+        context: &mut EnermyContext,
+    ) {
+        let (dt, resources) = (context.dt, context.resources); 
+        if *wind_up_timer > 0f32 {
+            if *wind_up_timer >= ENERMY_HOMING_WIND_UP_TIME {
+                resources.play_sound(SoundIdentifier::Warning, context.sound_queue, Volume(1.0f32)); 
+            }
+            *wind_up_timer -= dt; 
+
+            // back up a little and blink so the player sees the charge coming
+            pos.y -= ENERMY_HOMING_WIND_UP_RISE * dt; 
+            if (*wind_up_timer / ENERMY_HIT_FLASH_TIME) as i32 % 2 == 0 {
+                state_shared.hit_flash_timer = state_shared.hit_flash_timer.max(dt); 
+            }
+        } else {
+            *vel = Self::steer_homing(*pos, *vel, context.player_pos, dt); 
+            *pos += *vel * dt; 
         }
 
-        None
+        let ground_y = GAME_SIZE_Y as f32 - resources.ground_bg.height(); 
+        if pos.y + collider.rect.h > ground_y {
+            state_shared.health = 0; 
+            context.wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Environment; 
+        }
     }
 
-    fn update_state_shooting(
+    // seek the target with a limited turn rate and acceleration, easing off a bit on arrival
+    fn steer_homing(pos: Vec2, vel: Vec2, target: Vec2, dt: f32) -> Vec2 {
+        let to_target = target - pos; 
+        let distance = to_target.length(); 
+        let heading = if vel.length_squared() > 0f32 { vel.normalize() } else { vec2(0f32, 1f32) }; 
+        let desired_dir = if distance > 0f32 { to_target / distance } else { heading }; 
+
+        let mut desired_speed = ENERMY_HOMING_MAX_SPEED; 
+        if distance < ENERMY_HOMING_ARRIVAL_RADIUS {
+            desired_speed *= (distance / ENERMY_HOMING_ARRIVAL_RADIUS).max(ENERMY_HOMING_ARRIVAL_MIN_FRACTION); 
+        }
+
+        let cross = heading.x * desired_dir.y - heading.y * desired_dir.x; 
+        let angle = cross.atan2(heading.dot(desired_dir)); 
+        let max_turn = ENERMY_HOMING_TURN_RATE * dt; 
+        let (sin, cos) = angle.max(-max_turn).min(max_turn).sin_cos(); 
+        let heading = vec2(heading.x * cos - heading.y * sin, heading.x * sin + heading.y * cos); 
+
+        let speed = vel.length(); 
+        let max_change = ENERMY_HOMING_ACCELERATION * dt; 
+        let speed = speed + (desired_speed - speed).max(-max_change).min(max_change); 
+        heading * speed
+    }
+
+
+    fn update_state_boss(
         state_shared: &mut EnermyStateShared,
-        dt: f32,
-        bullets: &mut Vec<Bullet>,
-        resources: &Resources,
-        state_data: &mut EnermyStateShooting,
-        sound_mixer: &mut SoundMixer,
+        pos: &mut Vec2,
+        state_data: &mut EnermyStateBoss,
+        context: &mut EnermyContext,
     ) -> Option<EnermyCommand> {
-
-        state_shared.pos.x += rand::gen_range(-1f32, 1f32) * ENERMY_SPEED * 0.5f32 * dt;
-        state_shared.pos.y += rand::gen_range(-1f32, 1f32) * ENERMY_SPEED * 0.5f32 * dt;
-        Self::clamp_in_view(&mut state_shared.pos); 
-        state_data.shoot_timer -= dt;
-
-        if state_data.shoot_timer <= 0f32 {
-            state_data.shoot_timer = ENERMY_SHOOT_BURST_TIME; 
-            state_data.shots_left -= 1;
-
-            let should_spawn_2 = rand::gen_range(0, 2) > 1;
-            if should_spawn_2 {
-                let spawn_offset = vec2((state_shared.texture.width() / 4f32) * 0.5f32, 0f32); 
-                bullets.push(Bullet::new(state_shared.pos - spawn_offset, BulletHurtType::Player, resources))
-            }else {
-                let spawn_offset = vec2(0f32, -3f32);
-                bullets.push(Bullet::new(state_shared.pos + spawn_offset, BulletHurtType::Player, resources))
+        let (dt, resources) = (context.dt, context.resources); 
+        let phase_index = boss::phase_for_health(state_shared.health, state_shared.max_health); 
+        if phase_index != state_data.phase {
+            // give the player a breather before the next phase starts shooting
+            state_data.phase = phase_index; 
+            state_data.shoot_timer = BOSS_PHASE_CHANGE_PAUSE; 
+            state_data.runner_optional = None; 
+            resources.play_sound(SoundIdentifier::Warning, context.sound_queue, Volume(1.0f32)); 
+        }
+        let phase = BOSS_PHASES[state_data.phase]; 
+
+        // ease towards the path so phase changes don't teleport the boss
+        state_data.move_timer += dt * phase.speed; 
+        let target = boss::movement_target(phase.movement, state_data.move_timer); 
+        *pos += (target - *pos) * (BOSS_FOLLOW_SPEED * dt).min(1f32); 
+
+        // run the phase pattern, then wait shoot_time before running it again
+        match &mut state_data.runner_optional {
+            Some(runner) => {
+                let aim = context.aim_from(*pos); 
+                let bullets_fired = runner.update(dt, &aim, context.world, resources); 
+                if bullets_fired > 0 {
+                    resources.play_sound(SoundIdentifier::EnermyShoot, context.sound_queue, Volume(1.0f32)); 
+                }
+                if runner.is_finished() {
+                    state_data.runner_optional = None; 
+                    state_data.shoot_timer = phase.shoot_time; 
+                }
             }
+            None => {
+                state_data.shoot_timer -= dt; 
+                if state_data.shoot_timer <= 0f32 {
+                    state_data.runner_optional = resources.bullet_patterns.runner(phase.pattern); 
+                }
+            }
+        }
+
+        None
+    }
+
+    // returns false when the armor soaked up the hit
+    pub fn take_hit(&mut self, bullet_vel: Vec2, bullet_kind: BulletKind) -> bool {
+        let state_shared = &mut self.state_shared; 
+        let bullet_dir = if bullet_vel.length_squared() > 0f32 { bullet_vel.normalize() } else { vec2(0f32, -1f32) }; 
+
+        // a bullet flying straight against the armor facing hits it head on
+        let incoming_angle = (-bullet_dir).dot(state_shared.armor_facing).clamp(-1f32, 1f32).acos().to_degrees(); 
+        let blocked = incoming_angle <= state_shared.profile.armor_arc * 0.5f32 
+            || state_shared.profile.armor_kinds.contains(&bullet_kind); 
+        if state_shared.armor_hits > 0 && blocked {
+            state_shared.armor_hits -= 1; 
+            return false; 
+        }
+
+        state_shared.health -= 1; 
+        state_shared.hit_flash_timer = ENERMY_HIT_FLASH_TIME; 
+        if !matches!(state_shared.enermy_type, EnermyType::Boss) {
+            state_shared.knockback_vel = bullet_dir * ENERMY_KNOCKBACK_SPEED; 
+        }
+        true
+    }
 
-            resources.play_sound(SoundIdentifier::EnermyShoot, sound_mixer, Volume(1.0f32)); 
-            state_shared.pos.y -= 2f32;
+    fn turn_shield(state_shared: &mut EnermyStateShared, pos: Vec2, player_pos: &Vec2, dt: f32) {
+        let to_player = *player_pos - pos; 
+        if to_player.length_squared() <= 0f32 {
+            return; 
         }
 
+        let facing = state_shared.armor_facing; 
+        let desired = to_player.normalize(); 
+        let cross = facing.x * desired.y - facing.y * desired.x; 
+        let angle = cross.atan2(facing.dot(desired)); 
+        let max_turn = ENERMY_SHIELD_TURN_RATE * dt; 
+        let (sin, cos) = angle.max(-max_turn).min(max_turn).sin_cos(); 
+        state_shared.armor_facing = vec2(facing.x * cos - facing.y * sin, facing.x * sin + facing.y * cos); 
+    }
 
-        state_shared.collision_rect.x = state_shared.pos.x - state_shared.texture.width() * 0.5f32;
-        state_shared.collision_rect.y = state_shared.pos.y; 
+    fn update_hit_effects(state_shared: &mut EnermyStateShared, pos: &mut Vec2, dt: f32) {
+        state_shared.hit_flash_timer = (state_shared.hit_flash_timer - dt).max(0f32); 
 
-        state_shared.animation_timer += dt;
-        if state_shared.animation_timer > ENERMY_ANIM_TIME_FLAP * 4f32 {
-            state_shared.animation_timer -= ENERMY_ANIM_TIME_FLAP * 4f32; 
+        let knockback = state_shared.knockback_vel * dt; 
+        *pos += knockback; 
+        if let Some(path) = &mut state_shared.path_optional {
+            path.origin += knockback; 
         }
+        state_shared.knockback_vel *= (1f32 - ENERMY_KNOCKBACK_DAMPING * dt).max(0f32); 
+    }
+
+    // low health demons get darker so multi-hit ones show how close they are to dying
+    fn damage_tint(state_shared: &EnermyStateShared) -> Color {
+        if state_shared.max_health > 1 && state_shared.health * 2 <= state_shared.max_health {
+            ENERMY_DAMAGED_TINT
+        } else {
+            WHITE
+        }
+    }
 
-        if state_data.shots_left <= 0 { //dont shootin the player
-            return Some(EnermyCommand::ChangeState(EnermyState::Normal(
-                EnermyStateNormal { shoot_timer: 0f32 },
+    fn update_state_entering(
+        pos: &mut Vec2,
+        dt: f32,
+        state_data: &mut EnermyStateEntering,
+        formation: &Formation,
+    ) -> Option<EnermyCommand> {
+        state_data.timer += dt; 
+        let fraction = (state_data.timer / FORMATION_ENTER_TIME).min(1f32); 
+        let slot_pos = formation.slot_pos(state_data.slot); 
+        *pos = spline::bezier(
+            [state_data.start, state_data.controls[0], state_data.controls[1], slot_pos], 
+            fraction
+        ); 
+
+        if fraction >= 1f32 {
+            return Some(EnermyCommand::ChangeState(EnermyState::InFormation(
+                EnermyStateInFormation { slot: state_data.slot }
             )))
         }
         None
     }
 
-    fn update_state_homing (
-        state_shared: &mut EnermyStateShared,
-        dt: f32,
-        player_pos: &Vec2,
-        game_manager: &mut WaveManager,
-        sound_mixer: &mut SoundMixer,
-        resources: &Resources,
+    fn update_state_in_formation(
+        pos: &mut Vec2,
+        state_data: &mut EnermyStateInFormation,
+        formation: &Formation,
     ) -> Option<EnermyCommand> {
-        state_shared.animation_timer += dt;
-        if state_shared.animation_timer >= ENERMY_ANIM_TIME_FLAP * 4f32 {
-            state_shared.animation_timer -= ENERMY_ANIM_TIME_FLAP  * 4f32; 
-            resources.play_sound(SoundIdentifier::Warning, sound_mixer, Volume(1.0f32))
+        *pos = formation.slot_pos(state_data.slot); 
+        None
+    }
+
+    fn death_time(state_shared: &EnermyStateShared) -> f32 {
+        match state_shared.enermy_type {
+            EnermyType::Boss => ENERMY_DEATH_TIME * 3f32, 
+            _ => ENERMY_DEATH_TIME
         }
+    }
 
-        let player_dx = player_pos.x - state_shared.pos.x; 
-        let sway_speed = 20f32; 
-        let dx = if player_dx > 0f32 { 1f32 } else {-1f32 };
-        let sway = (get_time() as f32 * sway_speed).sin(); 
-        let sway = (sway + 1f32 ) * 0.5f32; 
+    fn new_state_dying(state_shared: &EnermyStateShared, collider: &Collider) -> EnermyStateDying {
+        let center = collider.center(); 
+        let (count, speed) = match state_shared.enermy_type {
+            EnermyType::Boss => (ENERMY_DEATH_PARTICLES * 4, ENERMY_DEATH_PARTICLE_SPEED * 1.5f32), 
+            _ => (ENERMY_DEATH_PARTICLES, ENERMY_DEATH_PARTICLE_SPEED)
+        }; 
 
-        let vel = vec2(dx * ENERMY_SPEED_HOMING.x * sway, ENERMY_SPEED_HOMING.y);
-        state_shared.pos += vel * dt;
+        let particles = (0..count).map(|_| {
+            let angle = rand::gen_range(0f32, std::f32::consts::PI * 2f32); 
+            DeathParticle {
+                pos: center, 
+                vel: vec2(angle.cos(), angle.sin()) * rand::gen_range(speed * 0.3f32, speed)
+            }
+        }).collect(); 
 
-        state_shared.collision_rect.x = state_shared.pos.x - state_shared.texture.width() * 0.5f32;
-        state_shared.collision_rect.y = state_shared.pos.y;
+        EnermyStateDying { timer: 0f32, center, particles }
+    }
 
-        if state_shared.pos.y > GAME_SIZE_X  as f32  {
-            state_shared.health = 0; 
-            game_manager.last_enermydeath_reason = LastEnermyDeathReason::Environment; 
+    fn update_state_dying(dt: f32, state_data: &mut EnermyStateDying) -> Option<EnermyCommand> {
+        state_data.timer += dt; 
+        let damping = (1f32 - ENERMY_DEATH_PARTICLE_DAMPING * dt).max(0f32); 
+        for particle in state_data.particles.iter_mut() {
+            particle.pos += particle.vel * dt; 
+            particle.vel *= damping; 
+        }
+        None
+    }
+
+    fn draw_state_dying(state_shared: &EnermyStateShared, state_data: &EnermyStateDying, texture_explosion: Texture2D) {
+        let fraction = (state_data.timer / Self::death_time(state_shared)).min(1f32); 
+        let mut color = state_shared.enermy_color.tint(); 
+
+        // the explosion sheet is a row of square frames
+        let anim_frames = (texture_explosion.width() / texture_explosion.height()).floor(); 
+        let frame = (fraction * anim_frames).min(anim_frames - 1f32).floor(); 
+        let frame_size = texture_explosion.height(); 
+        let size = match state_shared.enermy_type {
+            EnermyType::Boss => frame_size * BOSS_SCALE, 
+            _ => frame_size
+        }; 
+        let center = state_data.center; 
+        draw_texture_ex(
+            texture_explosion, 
+            center.x - size * 0.5f32, 
+            center.y - size * 0.5f32, 
+            color, 
+            DrawTextureParams {
+                dest_size: Some(vec2(size, size)), 
+                source: Some(Rect::new(frame_size * frame, 0f32, frame_size, frame_size)), 
+                ..Default::default()
+            }
+        ); 
+
+        color.a = 1f32 - fraction; 
+        for particle in state_data.particles.iter() {
+            draw_rectangle(particle.pos.x, particle.pos.y, 1f32, 1f32, color); 
+        }
+    }
+
+    fn update_state_diving(
+        state_shared: &mut EnermyStateShared,
+        pos: &mut Vec2,
+        state_data: &mut EnermyStateDiving,
+        context: &mut EnermyContext,
+    ) -> Option<EnermyCommand> {
+        let (dt, resources, player_pos) = (context.dt, context.resources, context.player_pos); 
+        state_data.timer += dt; 
+        let fraction = (state_data.timer / FORMATION_DIVE_TIME).min(1f32); 
+
+        // loop out sideways, then swoop down through where the player is now
+        let start = state_data.start; 
+        *pos = spline::bezier([
+            start, 
+            start + vec2(state_data.turn_dir * 40f32, -24f32), 
+            vec2(player_pos.x, GAME_SIZE_Y as f32 * 0.5f32), 
+            vec2(player_pos.x, GAME_SIZE_Y as f32 + 16f32)
+        ], fraction); 
+
+        if !state_data.has_shot && state_data.timer > FORMATION_DIVE_SHOOT_DELAY {
+            state_data.has_shot = true; 
+            state_data.runner_optional = state_shared.pattern_optional.as_ref()
+                .and_then(|pattern| resources.bullet_patterns.runner(pattern)); 
+        }
+        if let Some(runner) = &mut state_data.runner_optional {
+            let aim = context.aim_from(*pos); 
+            let bullets_fired = runner.update(dt, &aim, context.world, resources); 
+            if bullets_fired > 0 {
+                resources.play_sound(SoundIdentifier::EnermyShoot, context.sound_queue, Volume(1.0f32)); 
+            }
+        }
+
+        // off the bottom, come back in from the top
+        if fraction >= 1f32 {
+            let start = vec2(pos.x, -16f32); 
+            *pos = start; 
+            return Some(EnermyCommand::ChangeState(EnermyState::Entering(EnermyStateEntering {
+                slot: state_data.slot, 
+                start, 
+                controls: [start + vec2(0f32, 20f32), start + vec2(0f32, 30f32)], 
+                timer: 0f32
+            })))
         }
-        
         None
     }
 
+    pub fn draw(&self, transform: &Transform, collider: &Collider, animation: &Animation, resources: &Resources) {
+        let (pos, animation_timer) = (transform.pos, animation.timer); 
+        let flash_material_optional = match resources.flash_material_optional {
+            Some(flash_material) if self.state_shared.hit_flash_timer > 0f32 => Some(flash_material), 
+            _ => None
+        }; 
+        if let Some(flash_material) = flash_material_optional {
+            gl_use_material(flash_material); 
+        }
 
-    pub fn draw(&mut self) {
         match &self.state {
             EnermyState::Spawning(state_data) => {
-                Self::draw_state_spawning(&self.state_shared, state_data)
+                Self::draw_state_spawning(&self.state_shared, pos, animation_timer, state_data)
             }
 
-            EnermyState::Normal(state_data) => self.draw_state_normal(),
-            EnermyState::Shooting(state_data) => self.draw_state_normal(),
-            EnermyState::Homing(state_data) => self.draw_state_normal()
+            EnermyState::Behaviour(_state_data) => {
+                self.draw_state_normal(pos, animation_timer); 
+                Self::draw_aim_line(&self.state_shared, collider.center(), animation_timer); 
+            }
+            EnermyState::Boss(_state_data) => Self::draw_state_boss(&self.state_shared, pos, animation_timer, 1f32), 
+            EnermyState::Entering(_state_data) => self.draw_state_normal(pos, animation_timer),
+            EnermyState::InFormation(_state_data) => self.draw_state_normal(pos, animation_timer),
+            EnermyState::Diving(_state_data) => self.draw_state_normal(pos, animation_timer), 
+            EnermyState::Dying(state_data) => Self::draw_state_dying(&self.state_shared, state_data, resources.enermy_explosion)
+        }
+
+        if flash_material_optional.is_some() {
+            gl_use_default_material(); 
+        }
+
+        // a grey bar under demons that still have armor, shielded demons show the arc their shield covers
+        if self.state_shared.armor_hits > 0 && !matches!(self.state, EnermyState::Spawning(_) | EnermyState::Dying(_)) {
+            let rect = collider.rect; 
+            match self.state_shared.enermy_type {
+                EnermyType::Shielded => Self::draw_shield(&self.state_shared, collider.center()), 
+                _ => draw_line(rect.x + 1f32, rect.y + rect.h, rect.x + rect.w - 1f32, rect.y + rect.h, 1f32, GRAY)
+            }
+        }
+    }
+
+    fn draw_shield(state_shared: &EnermyStateShared, center: Vec2) {
+        let facing_angle = state_shared.armor_facing.y.atan2(state_shared.armor_facing.x); 
+        let half_arc = (state_shared.profile.armor_arc * 0.5f32).to_radians(); 
+        let segments = 6; 
+        let point = |i: i32| {
+            let angle = facing_angle - half_arc + half_arc * 2f32 * i as f32 / segments as f32; 
+            center + vec2(angle.cos(), angle.sin()) * ENERMY_SHIELD_RADIUS
+        }; 
+        for i in 0..segments {
+            let (a, b) = (point(i), point(i + 1)); 
+            draw_line(a.x, a.y, b.x, b.y, 1f32, LIGHTGRAY); 
         }
     }
-}
\ No newline at end of file
+
+    // runs on past the target to the edge of the screen, blinks once it stopped tracking
+    fn draw_aim_line(state_shared: &EnermyStateShared, start: Vec2, animation_timer: f32) {
+        let (target, locked) = match state_shared.aim_line_optional {
+            Some(aim_line) => aim_line, 
+            None => return
+        }; 
+        if locked && (animation_timer / ENERMY_ANIM_TIME_FLAP) as i32 % 2 == 0 {
+            return; 
+        }
+
+        let dir = target - start; 
+        if dir.length_squared() <= 0f32 {
+            return; 
+        }
+        let end = start + dir.normalize() * GAME_SIZE_X as f32 * 2f32; 
+        draw_line(start.x, start.y, end.x, end.y, 0.5f32, Color::new(1f32, 0.2f32, 0.2f32, 0.6f32)); 
+    }
+}
+
+
+
+// the rest of the game as every demon sees it this frame
+pub struct EnermyContext<'a> {
+    pub dt: f32, 
+    pub world: &'a mut World, 
+    pub resources: &'a Resources, 
+    pub player_pos: Vec2, 
+    pub player_vel: Vec2, 
+    pub wave_manager: &'a mut WaveManager, 
+    pub sound_queue: &'a mut SoundQueue, 
+}
+
+
+impl EnermyContext<'_> {
+    // demon patterns go for the player and only hurt the player
+    fn aim_from(&self, origin: Vec2) -> PatternAim {
+        PatternAim {
+            origin, 
+            target: self.player_pos, 
+            target_vel: self.player_vel, 
+            hurt_type: BulletHurtType::Player
+        }
+    }
+}
+
+
+// everything a behaviour tree needs to move and shoot one demon for a frame
+struct EnermyActor<'a, 'b> {
+    state_shared: &'a mut EnermyStateShared, 
+    pos: &'a mut Vec2, 
+    collider: &'a Collider, // still last frame's rect
+    context: &'a mut EnermyContext<'b>, 
+}
+
+
+impl BehaviourActor for EnermyActor<'_, '_> {
+    fn run(&mut self, action: &BehaviourAction, state: &mut ActionState, dt: f32) -> BehaviourStatus {
+        let started = state.started; 
+        state.started = true; 
+
+        match action {
+            BehaviourAction::Wait(time) => {
+                if !started {
+                    state.timer = time.eval(); 
+                }
+                state.count_down(dt)
+            }
+
+            BehaviourAction::Wander(time_optional) => {
+                if !started {
+                    state.timer = time_optional.as_ref().map(|time| time.eval()).unwrap_or(self.state_shared.profile.shoot_time); 
+                    if let Some(path) = &mut self.state_shared.path_optional {
+                        path.continue_from(*self.pos); 
+                    }
+                }
+                Enermy::update_wander(self.state_shared, self.pos, self.collider, dt, self.context.world); 
+                state.count_down(dt)
+            }
+
+            BehaviourAction::MoveTo(target, speed_optional) => {
+                let speed = speed_optional.unwrap_or(self.state_shared.profile.speed); 
+                let to_target = *target - *self.pos; 
+                let distance = to_target.length(); 
+                let arrived = distance <= speed * dt; 
+                if arrived {
+                    *self.pos = *target; 
+                } else {
+                    *self.pos += to_target / distance * speed * dt; 
+                }
+                if arrived { BehaviourStatus::Success } else { BehaviourStatus::Running }
+            }
+
+            BehaviourAction::ShootPattern(pattern_optional) => {
+                if !started {
+                    state.runner_optional = pattern_optional.as_ref()
+                        .or(self.state_shared.pattern_optional.as_ref())
+                        .and_then(|pattern| self.context.resources.bullet_patterns.runner(pattern)); 
+                    state.count = self.state_shared.profile.bursts; 
+                }
+                let runner = match &mut state.runner_optional {
+                    Some(runner) => runner, 
+                    None => return BehaviourStatus::Failure
+                }; 
+
+                let finished = Enermy::update_shooting(self.state_shared, self.pos, runner, &mut state.count, self.context); 
+                if finished { BehaviourStatus::Success } else { BehaviourStatus::Running }
+            }
+
+            BehaviourAction::Home => {
+                if !started {
+                    state.timer = ENERMY_HOMING_WIND_UP_TIME; 
+                }
+                Enermy::update_homing(self.state_shared, self.pos, self.collider, &mut state.timer, &mut state.vel, self.context); 
+                BehaviourStatus::Running
+            }
+
+            BehaviourAction::Dash => {
+                if !started {
+                    state.timer = ENERMY_KAMIKAZE_LOCK_TIME; 
+                    self.context.resources.play_sound(SoundIdentifier::Warning, self.context.sound_queue, Volume(1.0f32)); 
+                }
+
+                if state.timer > 0f32 {
+                    // tracking, the dash goes wherever the player was when the timer runs out
+                    state.timer -= dt; 
+                    state.target = self.context.player_pos; 
+                    self.state_shared.aim_line_optional = Some((state.target, false)); 
+                    if state.timer <= 0f32 {
+                        let dir = state.target - *self.pos; 
+                        let dir = if dir.length_squared() > 0f32 { dir.normalize() } else { vec2(0f32, 1f32) }; 
+                        state.vel = dir * ENERMY_KAMIKAZE_DASH_SPEED; 
+                        self.state_shared.aim_line_optional = None; 
+                    }
+                } else {
+                    *self.pos += state.vel * dt; 
+                }
+
+                let ground_y = GAME_SIZE_Y as f32 - self.context.resources.ground_bg.height(); 
+                let pos = *self.pos; 
+                let off_screen = pos.x < -ENERMY_KAMIKAZE_OFF_SCREEN_MARGIN
+                    || pos.x > GAME_SIZE_X as f32 + ENERMY_KAMIKAZE_OFF_SCREEN_MARGIN
+                    || pos.y < -ENERMY_KAMIKAZE_OFF_SCREEN_MARGIN
+                    || pos.y + self.collider.rect.h > ground_y; 
+                if off_screen {
+                    self.state_shared.health = 0; 
+                    self.context.wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Environment; 
+                }
+                BehaviourStatus::Running
+            }
+
+            BehaviourAction::Snipe => {
+                if !started {
+                    state.timer = ENERMY_SNIPER_AIM_TIME; 
+                }
+                state.timer -= dt; 
+
+                let locked = state.timer <= ENERMY_SNIPER_HOLD_TIME; 
+                if !locked {
+                    state.target = self.context.player_pos; 
+                }
+                self.state_shared.aim_line_optional = Some((state.target, locked)); 
+                if state.timer > 0f32 {
+                    return BehaviourStatus::Running; 
+                }
+
+                self.state_shared.aim_line_optional = None; 
+                // the collider only catches up after the update, shoot from where the demon is now
+                let rect = self.collider.rect_at(*self.pos); 
+                let start = rect.point() + rect.size() * 0.5f32; 
+                let dir = state.target - start; 
+                let dir = if dir.length_squared() > 0f32 { dir.normalize() } else { vec2(0f32, 1f32) }; 
+                bullet::spawn_moving(
+                    self.context.world, 
+                    start, 
+                    BulletMotion::straight(dir * ENERMY_SNIPER_SHOT_SPEED), 
+                    BulletHurtType::Player, 
+                    BulletKind::Missile, 
+                    self.context.resources
+                ); 
+                self.context.resources.play_sound(SoundIdentifier::EnermyShoot, self.context.sound_queue, Volume(1.0f32)); 
+                BehaviourStatus::Success
+            }
+
+            BehaviourAction::Flee(time) => {
+                if !started {
+                    state.timer = time.eval(); 
+                }
+                let away = *self.pos - self.context.player_pos; 
+                let dir = if away.length_squared() > 0f32 { away.normalize() } else { vec2(0f32, -1f32) }; 
+                *self.pos += dir * self.state_shared.profile.speed * dt; 
+                Enermy::clamp_in_view(self.pos); 
+                state.count_down(dt)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enermy_profile::EnermyProfileLibrary;
+
+    // four 16px frames, only the size is needed so nothing gets uploaded
+    fn sheet() -> Texture2D {
+        let mut texture = miniquad::Texture::empty();
+        texture.width = 64;
+        texture.height = 16;
+        Texture2D::from_miniquad_texture(texture)
+    }
+
+    fn plated(profile: &str, enermy_type: EnermyType) -> Enermy {
+        let mut profiles = EnermyProfileLibrary::new();
+        profiles.load(profile).unwrap();
+        Enermy::new(DemonSheet { texture: sheet(), mask_optional: None }, 3, EnermyDeathMethod::None, enermy_type, EnermyColor::PURPLE, profiles.get("plated"))
+    }
+
+    fn kamikaze() -> Enermy {
+        let mut profiles = EnermyProfileLibrary::new();
+        profiles.load(include_str!("../assets/profiles/demons.profile")).unwrap();
+        Enermy::new(DemonSheet { texture: sheet(), mask_optional: None }, 1, EnermyDeathMethod::None, EnermyType::Kamikaze, EnermyColor::RED, profiles.get("kamikaze"))
+    }
+
+    // the real red sheet and its mask, drawn BOSS_SCALE times bigger
+    fn boss() -> Enermy {
+        let image = Image::from_file_with_format(include_bytes!("../assets/demon_normal_red_1.png"), Some(ImageFormat::Png));
+        let mut texture = miniquad::Texture::empty();
+        texture.width = image.width as u32;
+        texture.height = image.height as u32;
+        let sheet = DemonSheet {
+            texture: Texture2D::from_miniquad_texture(texture),
+            mask_optional: Some(Rc::new(SpriteMask::from_image(&image, ENERMY_SPRITE_FRAMES)))
+        };
+        let mut profiles = EnermyProfileLibrary::new();
+        profiles.load(include_str!("../assets/profiles/demons.profile")).unwrap();
+        Enermy::new(sheet, 10, EnermyDeathMethod::None, EnermyType::Boss, EnermyColor::RED, profiles.get("red"))
+    }
+
+    // the collider after one update of a dash from start covering delta, the way the colliders system leaves it
+    fn dash(enermy: &Enermy, start: Vec2, delta: Vec2) -> Collider {
+        let mut collider = enermy.collider_at(start);
+        collider.prev_rect = collider.rect;
+        collider.rect = collider.rect_at(start + delta);
+        collider
+    }
+
+    fn animation() -> Animation {
+        Animation { timer: 0f32, loop_time_optional: Some(ENERMY_ANIM_TIME_FLAP * 4f32) }
+    }
+
+    #[test]
+    fn dash_sweeps_through_a_thin_player() {
+        let enermy = kamikaze();
+        let collider = dash(&enermy, vec2(100f32, 0f32), vec2(0f32, 120f32));
+        let player = Rect::new(104f32, 60f32, 8f32, 1f32);
+
+        assert!(!collider.rect.overlaps(&player));
+        assert!(collider.swept_rect().overlaps(&player));
+        assert!(collider.swept_rects().any(|rect| rect.overlaps(&player)));
+        assert!(enermy.sweep_overlaps(&collider, &animation(), &player));
+
+        let beside = Rect::new(130f32, 60f32, 8f32, 1f32);
+        assert!(!collider.swept_rects().any(|rect| rect.overlaps(&beside)));
+        assert!(!enermy.sweep_overlaps(&collider, &animation(), &beside));
+    }
+
+    #[test]
+    fn dash_swept_rects_end_where_the_demon_stopped() {
+        let enermy = kamikaze();
+        let collider = dash(&enermy, vec2(100f32, 0f32), vec2(-45f32, 90f32));
+        let last = collider.swept_rects().last().unwrap();
+        assert!((last.point() - collider.rect.point()).length() < 1e-4);
+        assert_eq!(last.size(), vec2(16f32, 16f32));
+    }
+
+    #[test]
+    fn boss_rect_is_centred_from_the_spawn() {
+        let rect = boss().collider_at(vec2(100f32, 20f32)).rect;
+        assert_eq!(rect.x + rect.w * 0.5f32, 100f32);
+        assert_eq!(rect.y, 20f32);
+    }
+
+    #[test]
+    fn boss_hits_follow_the_scaled_mask() {
+        let enermy = boss();
+        let collider = enermy.collider_at(vec2(100f32, 20f32));
+        let rect = collider.rect;
+        let mask = enermy.state_shared.mask_optional.clone().unwrap();
+        // a speck inside one sheet pixel, scaled up to where the boss draws it
+        let speck = |(x, y): (i32, i32)| Rect::new(
+            rect.x + (x as f32 + 0.25f32) * BOSS_SCALE,
+            rect.y + (y as f32 + 0.25f32) * BOSS_SCALE,
+            1f32,
+            1f32
+        );
+        let pixels = || (0..mask.frame_height).flat_map(|y| (0..mask.frame_width).map(move |x| (x, y)));
+        let solid = pixels().find(|(x, y)| mask.is_solid(0, *x, *y)).unwrap();
+        let empty = pixels().find(|(x, y)| !mask.is_solid(0, *x, *y)).unwrap();
+
+        assert!(enermy.overlaps(&collider, &animation(), &speck(solid)));
+        assert!(rect.overlaps(&speck(empty)));
+        assert!(!enermy.overlaps(&collider, &animation(), &speck(empty)));
+    }
+
+    #[test]
+    fn armor_soaks_hits_from_within_its_arc() {
+        let mut enermy = plated("(profile plated (armor 90 2))", EnermyType::Shielded);
+        let from_below = vec2(0f32, -100f32);
+        let from_the_side = vec2(-100f32, 0f32);
+
+        assert!(!enermy.take_hit(from_below, BulletKind::Missile));
+        assert!(enermy.take_hit(from_the_side, BulletKind::Missile));
+        assert_eq!((enermy.state_shared.armor_hits, enermy.state_shared.health), (1, 2));
+
+        // turned towards the bullet, the side shot now lands on the shield
+        enermy.state_shared.armor_facing = vec2(1f32, 0f32);
+        assert!(!enermy.take_hit(from_the_side, BulletKind::Missile));
+        assert!(enermy.take_hit(from_the_side, BulletKind::Missile));
+        assert_eq!((enermy.state_shared.armor_hits, enermy.state_shared.health), (0, 1));
+    }
+
+    #[test]
+    fn armor_soaks_listed_kinds_from_anywhere() {
+        let mut enermy = plated("(profile plated (armor 0 2 orb))", EnermyType::NORMAL);
+        let from_above = vec2(0f32, 100f32);
+
+        assert!(enermy.take_hit(from_above, BulletKind::Missile));
+        assert!(!enermy.take_hit(from_above, BulletKind::Orb));
+        assert!(!enermy.take_hit(vec2(100f32, 0f32), BulletKind::Orb));
+        assert_eq!(enermy.state_shared.armor_hits, 0);
+
+        // broken, nothing gets soaked anymore
+        assert!(enermy.take_hit(from_above, BulletKind::Orb));
+        assert_eq!(enermy.state_shared.health, 1);
+    }
+}
@@ -0,0 +1,229 @@
+use macroquad::prelude::*;
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    bullet::BulletKind,
+    enermy::EnermyDeathMethod,
+    sexpr::{self, Sexpr}
+};
+
+
+// how a demon of one color or archetype moves, shoots and dies, loaded from assets/profiles/demons.profile
+pub struct EnermyProfile {
+    pub speed: f32,
+    pub shoot_time: f32, // time in the normal state before shooting
+    pub bursts: i32, // how often the pattern runs back to back every time it shoots
+    pub patterns: Vec<(String, f32)>, // pattern name and weight
+    pub paths: Vec<(String, f32)>, // path name and weight, see assets/waves/demons.wave
+    pub children_chance: f32,
+    pub children_min: i32,
+    pub children_max: i32,
+    pub dodge_speed: f32, // 0 never dodges
+    pub dodge_range: f32, // how close a player bullet has to be before dodging
+    pub armor_arc: f32, // degrees around straight below that the armor covers
+    pub armor_hits: i32, // hits the armor soaks up before it breaks
+    pub armor_kinds: Vec<BulletKind>, // soaked up whichever way they come in
+    pub behaviour: String, // tree from assets/behaviours/demons.behaviour
+    pub mini_behaviour: String // tree the minis it splits into run
+}
+
+
+impl EnermyProfile {
+    fn new() -> Self {
+        EnermyProfile {
+            speed: 50f32,
+            shoot_time: 2f32,
+            bursts: 1,
+            patterns: Vec::new(),
+            paths: Vec::new(),
+            children_chance: 0f32,
+            children_min: 1,
+            children_max: 1,
+            dodge_speed: 0f32,
+            dodge_range: 0f32,
+            armor_arc: 0f32,
+            armor_hits: 0,
+            armor_kinds: Vec::new(),
+            behaviour: String::from("normal"),
+            mini_behaviour: String::from("mini")
+        }
+    }
+
+    pub fn pick_pattern(&self) -> Option<String> {
+        pick_weighted(&self.patterns)
+    }
+
+    pub fn pick_path(&self) -> Option<String> {
+        pick_weighted(&self.paths)
+    }
+
+    pub fn roll_death_method(&self) -> EnermyDeathMethod {
+        if rand::gen_range(0f32, 1f32) < self.children_chance {
+            let spawn_amount = rand::gen_range(self.children_min, self.children_max + 1);
+            EnermyDeathMethod::SpawnChildren(spawn_amount)
+        } else {
+            EnermyDeathMethod::None
+        }
+    }
+}
+
+
+pub struct EnermyProfileLibrary {
+    profiles: HashMap<String, Rc<EnermyProfile>>
+}
+
+
+impl EnermyProfileLibrary {
+    pub fn new() -> Self {
+        EnermyProfileLibrary {
+            profiles: HashMap::new()
+        }
+    }
+
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        for expr in sexpr::parse(source)?.iter() {
+            let (head, args) = expr.as_call()?;
+            if head != "profile" {
+                return Err(format!("expected `profile`, found `{}`", head));
+            }
+
+            let (name, settings) = args.split_first().ok_or("profile without a name")?;
+            let name = name.as_atom()?;
+            let profile = compile_profile(settings).map_err(|e| format!("profile `{}`: {}", name, e))?;
+            self.profiles.insert(String::from(name), Rc::new(profile));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.profiles.contains_key(name)
+    }
+
+    pub fn pattern_names(&self) -> Vec<&str> {
+        self.profiles.values()
+            .flat_map(|profile| profile.patterns.iter().map(|(name, _)| name.as_str()))
+            .collect()
+    }
+
+    pub fn path_names(&self) -> Vec<&str> {
+        self.profiles.values()
+            .flat_map(|profile| profile.paths.iter().map(|(name, _)| name.as_str()))
+            .collect()
+    }
+
+    pub fn behaviour_names(&self) -> Vec<&str> {
+        self.profiles.values()
+            .flat_map(|profile| vec![profile.behaviour.as_str(), profile.mini_behaviour.as_str()])
+            .collect()
+    }
+
+    // every color and archetype is checked on startup, so this can't miss
+    pub fn get(&self, name: &str) -> Rc<EnermyProfile> {
+        self.profiles[name].clone()
+    }
+}
+
+
+fn pick_weighted(choices: &[(String, f32)]) -> Option<String> {
+    let weight_total: f32 = choices.iter().map(|(_, weight)| weight).sum();
+    if weight_total <= 0f32 {
+        return None;
+    }
+
+    let mut roll = rand::gen_range(0f32, weight_total);
+    for (name, weight) in choices.iter() {
+        if roll < *weight {
+            return Some(name.clone());
+        }
+        roll -= weight;
+    }
+    choices.last().map(|(name, _)| name.clone())
+}
+
+
+fn compile_profile(settings: &[Sexpr]) -> Result<EnermyProfile, String> {
+    let mut profile = EnermyProfile::new();
+
+    for setting in settings.iter() {
+        match setting.as_call()? {
+            ("speed", [value]) => profile.speed = value.as_f32()?,
+            ("shoot-time", [value]) => profile.shoot_time = value.as_f32()?,
+            ("bursts", [value]) => profile.bursts = value.as_f32()? as i32,
+            ("pattern", [name]) => profile.patterns.push((String::from(name.as_atom()?), 1f32)),
+            ("pattern", [name, weight]) => profile.patterns.push((String::from(name.as_atom()?), weight.as_f32()?)),
+            ("path", [name]) => profile.paths.push((String::from(name.as_atom()?), 1f32)),
+            ("path", [name, weight]) => profile.paths.push((String::from(name.as_atom()?), weight.as_f32()?)),
+            ("children", [chance, min, max]) => {
+                profile.children_chance = chance.as_f32()?;
+                profile.children_min = min.as_f32()? as i32;
+                profile.children_max = max.as_f32()? as i32;
+                if profile.children_max < profile.children_min {
+                    return Err(String::from("children max is smaller than min"));
+                }
+            }
+            ("dodge", [speed, range]) => {
+                profile.dodge_speed = speed.as_f32()?;
+                profile.dodge_range = range.as_f32()?;
+            }
+            ("armor", [arc, hits, kinds @ ..]) => {
+                profile.armor_arc = arc.as_f32()?;
+                profile.armor_hits = hits.as_f32()? as i32;
+                profile.armor_kinds = kinds.iter()
+                    .map(|kind| BulletKind::from_name(kind.as_atom()?))
+                    .collect::<Result<_, _>>()?;
+            }
+            ("behaviour", [name]) => profile.behaviour = String::from(name.as_atom()?),
+            ("mini-behaviour", [name]) => profile.mini_behaviour = String::from(name.as_atom()?),
+            (head, args) => return Err(format!("unknown setting `{}` with {} arguments", head, args.len()))
+        }
+    }
+    Ok(profile)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(source: &str) -> Result<EnermyProfileLibrary, String> {
+        let mut library = EnermyProfileLibrary::new();
+        library.load(source)?;
+        Ok(library)
+    }
+
+    #[test]
+    fn reads_settings_over_the_defaults() {
+        let library = load("(profile plated (speed 30) (pattern burst 3) (pattern spiral) (armor 90 2 missile orb))").unwrap();
+        let profile = library.get("plated");
+        assert_eq!(profile.speed, 30f32);
+        assert_eq!(profile.shoot_time, 2f32);
+        assert_eq!(profile.patterns, vec![(String::from("burst"), 3f32), (String::from("spiral"), 1f32)]);
+        assert_eq!(profile.armor_arc, 90f32);
+        assert_eq!(profile.armor_hits, 2);
+        assert!(profile.armor_kinds == vec![BulletKind::Missile, BulletKind::Orb]);
+        assert_eq!(profile.behaviour, "normal");
+        assert!(!library.contains("purple"));
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_wrong_arity() {
+        assert_eq!(load("(demon red)").err().unwrap(), "expected `profile`, found `demon`");
+        assert_eq!(load("(profile)").err().unwrap(), "profile without a name");
+        assert_eq!(load("(profile red (speed 1 2))").err().unwrap(), "profile `red`: unknown setting `speed` with 2 arguments");
+        assert_eq!(load("(profile red (teleport 1))").err().unwrap(), "profile `red`: unknown setting `teleport` with 1 arguments");
+        assert_eq!(load("(profile red (armor 90))").err().unwrap(), "profile `red`: unknown setting `armor` with 1 arguments");
+        assert_eq!(load("(profile red (armor 90 2 laser))").err().unwrap(), "profile `red`: unknown bullet kind `laser`");
+        assert_eq!(load("(profile red (children 0.5 3 1))").err().unwrap(), "profile `red`: children max is smaller than min");
+        assert!(load("(profile red (speed fast))").is_err());
+    }
+
+    #[test]
+    fn picks_only_weighted_choices() {
+        let library = load("(profile red (pattern never 0) (pattern aimed 1)) (profile still)").unwrap();
+        rand::srand(7);
+        for _ in 0..100 {
+            assert_eq!(library.get("red").pick_pattern().unwrap(), "aimed");
+        }
+        assert!(library.get("still").pick_path().is_none());
+    }
+}
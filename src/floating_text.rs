@@ -0,0 +1,86 @@
+use macroquad::prelude::*;
+
+use crate::constants::*; 
+
+
+pub struct FloatingText {
+    text: String, 
+    pos: Vec2, 
+    vel: Vec2, 
+    color: Color, 
+    timer: f32, 
+    lifetime: f32
+}
+
+
+// short lived world space text, used for score pop ups and other kill feedback
+pub struct FloatingTextManager {
+    texts: Vec<FloatingText>
+}
+
+
+impl FloatingTextManager {
+    pub fn new() -> Self {
+        FloatingTextManager {
+            texts: Vec::<FloatingText>::new()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.texts.clear(); 
+    }
+
+    pub fn spawn(&mut self, text: String, pos: Vec2, color: Color) {
+        self.spawn_ex(text, pos, color, FLOATING_TEXT_LIFETIME); 
+    }
+
+    pub fn spawn_ex(&mut self, text: String, pos: Vec2, color: Color, lifetime: f32) {
+        // keep pop ups spawned on the same spot from covering each other
+        let stack_offset = self.texts.iter()
+            .filter(|t| t.timer < FLOATING_TEXT_STACK_TIME && t.pos.distance(pos) < FLOATING_TEXT_FONT_SIZE as f32)
+            .count() as f32 * FLOATING_TEXT_FONT_SIZE as f32; 
+
+        self.texts.push(FloatingText {
+            text, 
+            pos: pos - vec2(0f32, stack_offset), 
+            vel: vec2(rand::gen_range(-4f32, 4f32), -FLOATING_TEXT_SPEED), 
+            color, 
+            timer: 0f32, 
+            lifetime
+        }); 
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for text in self.texts.iter_mut() {
+            text.timer += dt; 
+            text.pos += text.vel * dt; 
+            text.vel *= 1f32 - FLOATING_TEXT_DAMPING * dt; 
+        }
+
+        self.texts.retain(|t| t.timer < t.lifetime); 
+    }
+
+    pub fn draw(&self, font: Font) {
+        for text in self.texts.iter() {
+            let fraction = text.timer / text.lifetime; 
+            let mut color = text.color; 
+            // stay solid for the first half, then fade out
+            color.a = (1f32 - (fraction - 0.5f32).max(0f32) * 2f32) * text.color.a; 
+
+            let text_width = text.text.len() as f32 * FLOATING_TEXT_FONT_SIZE as f32 * 0.6f32; 
+            let x = (text.pos.x - text_width * 0.5f32).max(0f32).min(GAME_SIZE_X as f32 - text_width); 
+            draw_text_ex(
+                text.text.as_ref(), 
+                x.floor(), 
+                text.pos.y.floor(), 
+                TextParams {
+                    font, 
+                    font_size: FLOATING_TEXT_FONT_SIZE,
+                    font_scale: 1f32,
+                    color,
+                    font_scale_aspect: 1f32
+                }
+            ); 
+        }
+    }
+}
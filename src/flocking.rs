@@ -0,0 +1,55 @@
+use macroquad::prelude::*;
+
+use crate::{
+    constants::*,
+    ecs::World
+};
+
+
+// pushes demons apart that got too close, so they don't stack on top of each other
+// centers and offsets are scratch, one per demon in spawn order
+pub fn separate(world: &mut World, centers: &mut Vec<Option<Vec2>>, offsets: &mut Vec<Vec2>, dt: f32) {
+    centers.clear();
+    centers.extend(world.enermy_entities()
+        .map(|entity| match world.enermies.get(entity).unwrap().is_separated() {
+            true => Some(world.colliders.get(entity).unwrap().center()),
+            false => None
+        }));
+
+    offsets.clear();
+    offsets.resize(centers.len(), vec2(0f32, 0f32));
+    for i in 0..centers.len() {
+        let center = match centers[i] {
+            Some(center) => center,
+            None => continue
+        };
+
+        for j in (i + 1)..centers.len() {
+            let other_center = match centers[j] {
+                Some(other_center) => other_center,
+                None => continue
+            };
+
+            let away = center - other_center;
+            let distance = away.length();
+            if distance >= ENERMY_SEPARATION_RADIUS {
+                continue;
+            }
+
+            // right on top of each other, pick a side so they still split up
+            let dir = if distance > 0f32 { away / distance } else { vec2(if i % 2 == 0 { 1f32 } else { -1f32 }, 0f32) };
+            let strength = 1f32 - distance / ENERMY_SEPARATION_RADIUS;
+            let push = dir * strength * ENERMY_SEPARATION_SPEED * dt * 0.5f32;
+            offsets[i] += push;
+            offsets[j] -= push;
+        }
+    }
+
+    for (index, offset) in offsets.iter().enumerate() {
+        if offset.length_squared() > 0f32 {
+            let entity = world.enermy_at(index);
+            let transform = world.transforms.get_mut(entity).unwrap();
+            world.enermies.get_mut(entity).unwrap().nudge(transform, *offset);
+        }
+    }
+}
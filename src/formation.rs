@@ -0,0 +1,73 @@
+use macroquad::prelude::*;
+
+use crate::{
+    constants::*,
+    ecs::{Entity, World}
+};
+
+
+// galaga style grid the enemies of a formation wave fly into, sways as a group and sends out divers
+pub struct Formation {
+    occupied: Vec<bool>, // rebuilt from the enemies every frame, so dead enemies free their slot
+    sway_timer: f32,
+    dive_timer: f32
+}
+
+
+impl Formation {
+    pub fn new() -> Self {
+        Formation {
+            occupied: vec![false; (FORMATION_COLUMNS * FORMATION_ROWS) as usize],
+            sway_timer: 0f32,
+            dive_timer: FORMATION_DIVE_INTERVAL
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn slot_pos(&self, slot: usize) -> Vec2 {
+        let column = (slot as i32 % FORMATION_COLUMNS) as f32;
+        let row = (slot as i32 / FORMATION_COLUMNS) as f32;
+        let width = (FORMATION_COLUMNS - 1) as f32 * FORMATION_SPACING.x;
+        let sway = self.sway_timer.sin() * FORMATION_SWAY_X;
+
+        vec2(
+            GAME_CENTER_X - width * 0.5f32 + column * FORMATION_SPACING.x + sway,
+            FORMATION_TOP + row * FORMATION_SPACING.y
+        )
+    }
+
+    // lowest free slot, so the top row fills first, left to right
+    pub fn claim_slot(&mut self) -> Option<usize> {
+        let slot = (0..self.occupied.len()).find(|slot| !self.occupied[*slot])?;
+        self.occupied[slot] = true;
+        Some(slot)
+    }
+
+    pub fn update(&mut self, dt: f32, world: &mut World) {
+        self.sway_timer += dt * FORMATION_SWAY_SPEED;
+
+        for occupied in self.occupied.iter_mut() {
+            *occupied = false;
+        }
+        for slot in world.enermies().filter_map(|enemy| enemy.formation_slot_optional()) {
+            self.occupied[slot] = true;
+        }
+
+        // every so often one of the settled enemies peels off for a dive-bomb run
+        self.dive_timer -= dt;
+        if self.dive_timer <= 0f32 {
+            self.dive_timer += FORMATION_DIVE_INTERVAL;
+            let is_diver = |entity: &Entity| world.enermies.get(*entity).unwrap().is_in_formation();
+            let diver_count = world.enermy_entities().filter(is_diver).count();
+            if diver_count > 0 {
+                let index = rand::gen_range(0, diver_count);
+                let diver = world.enermy_entities().filter(is_diver).nth(index).unwrap();
+                let pos = world.transforms.get(diver).unwrap().pos;
+                world.enermies.get_mut(diver).unwrap().start_dive(pos);
+            }
+        }
+    }
+}
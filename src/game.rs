@@ -4,14 +4,20 @@ use quad_snd::mixer::{ SoundMixer, Volume };
 
 use crate::{
     constants::*,
-    resources::{Resources, SoundIdentifier}, 
-    player::{Player, PlayerState, PlayerCommand}, 
-    enermy::{Enermy, EnermyColor, EnermyType, EnermyState, EnermyStateHoming, EnermyDeathMethod}, 
-    variant_eq,
+    resources::{Resources, SoundIdentifier, SoundQueue}, 
+    player::{Player, PlayerState, PlayerCommand, PlayerControls, PlayerInput, PlayerMessage}, 
+    enermy::{EnermyType, EnermyDeathMethod}, 
+    game_camera,
     wave::{
-        WaveManagerState, LastEnermyDeathReason, WaveManager, WaveManagerMessage, spawn_enermy, SpawnBlueprint
+        WaveManagerState, LastEnermyDeathReason, WaveManager, WaveManagerMessage, SpawnBlueprint
     }, 
-    bullet::{Bullet, BulletHurtType},
+    bullet::BulletHurtType,
+    ecs::{Collider, Entity, Transform, World},
+    systems::{self, GameEvent, SystemBuffers},
+    combo::Combo,
+    floating_text::FloatingTextManager,
+    bunker::BunkerManager,
+    spatial_hash::SpatialHash,
     
 };
 
@@ -25,12 +31,20 @@ pub struct MenuPayload {
 
 
 
+pub struct VersusPayload {
+    winner: VersusSide, 
+}
+
+
+
 pub struct GameStateMenu {
-    last_score_optional: Option<i32>
+    last_score_optional: Option<i32>, 
+    last_winner_optional: Option<VersusSide>
 }
 
 pub enum ChangeStatePayload {
     MenuPayload(MenuPayload),
+    VersusPayload(VersusPayload),
 }
 
 pub enum GameStateCommand {
@@ -41,7 +55,8 @@ pub enum GameStateCommand {
 impl GameStateMenu {
     pub fn new() -> Self {
         GameStateMenu {
-            last_score_optional: None
+            last_score_optional: None, 
+            last_winner_optional: None
         }
     }
 }
@@ -89,7 +104,7 @@ pub fn draw_lives(player_lives: &i32, texture_life: Texture2D, texture_ground_bg
 }
 
 impl GameState for GameStateMenu {
-    fn draw(&self, resources: &Resources) {
+    fn draw(&mut self, resources: &Resources) {
         draw_texture_ex(
             resources.ground_bg, 
             0f32,
@@ -102,11 +117,15 @@ impl GameState for GameStateMenu {
         )
     }
 
-    fn update(&mut self, dt: f32, resources: &Resources, sound_mixer: &mut SoundMixer) -> Option<GameStateCommand>{
+    fn update(&mut self, dt: f32, resources: &Resources, sound_queue: &mut SoundQueue) -> Option<GameStateCommand>{
         if is_key_pressed(KEY_START_GAME){
             return Some(GameStateCommand::ChangeState(GameStateIdentifier::Game, None,))
         }
 
+        if is_key_pressed(KEY_START_VERSUS){
+            return Some(GameStateCommand::ChangeState(GameStateIdentifier::Versus, None,))
+        }
+
         None
     }
 
@@ -114,7 +133,12 @@ impl GameState for GameStateMenu {
         if let Some(payload) = payload_optional {
             match payload {
                 ChangeStatePayload::MenuPayload(menu_payload) => {
-                    self.last_score_optional = Some(menu_payload.score)
+                    self.last_score_optional = Some(menu_payload.score); 
+                    self.last_winner_optional = None; 
+                }
+                ChangeStatePayload::VersusPayload(versus_payload) => {
+                    self.last_score_optional = None; 
+                    self.last_winner_optional = Some(versus_payload.winner); 
                 }
             }
         }
@@ -150,6 +174,28 @@ impl GameState for GameStateMenu {
                 },
             );
         }
+
+        if let Some(last_winner) = &self.last_winner_optional {
+            let winner_text = match last_winner {
+                VersusSide::Left => "PLAYER 1 WINS", 
+                VersusSide::Right => "PLAYER 2 WINS"
+            };
+            let mut text_x = width_padding + scaled_game_size_w * 0.5f32;
+            text_x -= winner_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
+            draw_text_ex(
+                winner_text,
+                text_x,
+                height_padding + font_size as f32 * 2f32,
+                TextParams {
+                    font: resources.font,
+                    font_size,
+                    font_scale: 1f32,
+                    color: YELLOW,
+                    font_scale_aspect: 1f32,
+                },
+            );
+        }
+
         let start_text = "TAP SPACE TO START";
         let mut text_x = width_padding + scaled_game_size_w * 0.5f32;
         text_x -= start_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
@@ -166,6 +212,23 @@ impl GameState for GameStateMenu {
                 font_scale_aspect: 1f32,
             },
         );
+
+        let versus_text = "TAP V FOR VERSUS";
+        let mut text_x = width_padding + scaled_game_size_w * 0.5f32;
+        text_x -= versus_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
+
+        draw_text_ex(
+            versus_text,
+            text_x,
+            screen_height() * 0.5f32 + font_size as f32 * 1.5f32,
+            TextParams {
+                font: resources.font,
+                font_size,
+                font_scale: 1f32,
+                color: YELLOW,
+                font_scale_aspect: 1f32,
+            },
+        );
     
     }
     
@@ -176,9 +239,9 @@ pub trait GameState {
         &mut self, 
         dt: f32, 
         resources: &Resources, 
-        sound_mixer: &mut SoundMixer, 
+        sound_queue: &mut SoundQueue, 
     ) -> Option<GameStateCommand>; 
-    fn draw(&self, resources: &Resources); 
+    fn draw(&mut self, resources: &Resources); 
     fn draw_unscaled(&self, resources: &Resources); 
     fn on_enter(&mut self, resources: &Resources, payload_optional: Option<ChangeStatePayload>);
 }
@@ -187,49 +250,481 @@ pub trait GameState {
 #[derive(PartialEq, Eq, Hash)]
 pub enum GameStateIdentifier {
     Menu, 
-    Game
+    Game, 
+    Versus
+}
+
+
+// everything the game reads from the keyboard in one frame, so the replay tests can script it
+#[derive(Clone, Copy, Default)]
+pub struct GameInput {
+    pub player: PlayerInput, 
+    pub continue_pressed: bool
 }
 
 
 pub struct GameStateGame {
-    player_score: i32, 
-    player_lives: i32, 
-    player: Player, 
-    enermies: Vec<Enermy>, 
-    bullet: Vec<Bullet>,
-    wave_manager: WaveManager
+    pub player_score: i32, 
+    pub player_lives: i32, 
+    player_entity: Entity, 
+    controls: PlayerControls, 
+    world: World, // the player, the demons and the bullets
+    wave_manager: WaveManager, 
+    attacks_enabled: bool, 
+    outgoing_attacks: Vec<SpawnBlueprint>, // spawns to send to the opponent in versus mode
+    combo: Combo, 
+    broods: HashMap<u32, Brood>, 
+    next_brood_id: u32, 
+    floating_texts: FloatingTextManager, 
+    next_extra_life_index: usize, 
+    continues_max: i32, 
+    continues_left: i32, 
+    continue_timer_optional: Option<f32>, // counting down while waiting for the player to continue
+    debug_paths: bool, 
+    debug_pool: bool, 
+    bunkers: BunkerManager, 
+    bullet_hash: SpatialHash<Entity>, 
+    enermy_hash: SpatialHash<Entity>, 
+    system_buffers: SystemBuffers, 
+    events: Vec<GameEvent> // what the collision systems found this frame, emptied once it has been scored
+}
+
+
+// the minis spawned by one demon dying, killing all of them gives a bonus
+pub struct Brood {
+    children_left: i32, 
+    broken: bool // a child got away or died to something other than the player
 }
 
 
 
 //implementaation 
 impl GameStateGame {
-    pub fn new(resources: &Resources) -> Self {
+    pub fn new(resources: &Resources, controls: PlayerControls) -> Self {
+        let mut world = World::new(); 
+        let player_entity = Self::spawn_player(&mut world, resources); 
+
+        GameStateGame {
+            player_score: 0, 
+            player_lives: PLAYER_LIVES_START, 
+            player_entity, 
+            controls, 
+            world, 
+            wave_manager: WaveManager::new(), 
+            attacks_enabled: false, 
+            outgoing_attacks: Vec::<SpawnBlueprint>::new(), 
+            combo: Combo::new(), 
+            broods: HashMap::new(), 
+            next_brood_id: 0, 
+            floating_texts: FloatingTextManager::new(), 
+            next_extra_life_index: 0, 
+            continues_max: CONTINUES_MAX, 
+            continues_left: CONTINUES_MAX, 
+            continue_timer_optional: None, 
+            debug_paths: false, 
+            debug_pool: false, 
+            bunkers: BunkerManager::new(resources), 
+            bullet_hash: SpatialHash::new(SPATIAL_HASH_CELL_SIZE), 
+            enermy_hash: SpatialHash::new(SPATIAL_HASH_CELL_SIZE), 
+            system_buffers: SystemBuffers::new(), 
+            events: Vec::new()
+        }
+
+    }
+
+    fn spawn_player(world: &mut World, resources: &Resources) -> Entity {
         let player_spawn_y = GAME_SIZE_X as f32 - resources.ground_bg.height() - resources.ground_bg.width(); 
         let player_pos = vec2(GAME_CENTER_X, player_spawn_y); 
 
         let player = Player::new(
-            player_pos, 
             resources.player, 
             resources.player_missle, 
             resources.player_explosion
         );
+        let entity = world.spawn(); 
+        world.insert_transform(entity, Transform { pos: player_pos, rotation: 0f32 }); 
+        world.insert_collider(entity, Collider::at(player_pos, PLAYER_HITBOX_OFFSET, vec2(PLAYER_HITBOX_SIZE, PLAYER_HITBOX_SIZE))); 
+        world.insert_player(entity, player); 
+        entity
+    }
 
-        GameStateGame {
-            player_score: 0, 
-            player_lives: PLAYER_LIVES_START, 
-            player, 
-            bullet: Vec::<Bullet>::new(), 
-            enermies: Vec::<Enermy>::new(), 
-            wave_manager: WaveManager::new()
+    fn player(&self) -> &Player {
+        self.world.players.get(self.player_entity).unwrap()
+    }
+
+    fn player_pos(&self) -> Vec2 {
+        self.world.transforms.get(self.player_entity).unwrap().pos
+    }
+
+    fn player_mut(&mut self) -> &mut Player {
+        self.world.players.get_mut(self.player_entity).unwrap()
+    }
+
+    pub fn take_outgoing_attacks(&mut self) -> Vec<SpawnBlueprint> {
+        std::mem::take(&mut self.outgoing_attacks)
+    }
+
+    fn send_attack(&mut self, amount: i32) {
+        if !self.attacks_enabled {
+            return; 
+        }
+        for _ in 0..amount {
+            self.outgoing_attacks.push(SpawnBlueprint::Normal); 
+        }
+    }
+
+    fn register_kill(&mut self, score: i32, pos: Vec2) {
+        let chain = self.combo.register_kill(); 
+        let score_add = score * self.combo.multiplier(); 
+        self.player_score += score_add; 
+
+        self.floating_texts.spawn(format!("+{}", score_add), pos, WHITE); 
+        if chain > 1 {
+            self.floating_texts.spawn(format!("CHAIN x{}", chain), pos, ORANGE); 
+        }
+
+        if chain % VERSUS_CHAIN_KILLS_PER_ATTACK == 0 {
+            self.send_attack(1); 
+        }
+    }
+
+    fn extra_life_score(index: usize) -> i32 {
+        let last_index = SCORE_EXTRA_LIFE_THRESHOLDS.len() - 1; 
+        if index <= last_index {
+            return SCORE_EXTRA_LIFE_THRESHOLDS[index]; 
+        }
+        SCORE_EXTRA_LIFE_THRESHOLDS[last_index] + (index - last_index) as i32 * SCORE_EXTRA_LIFE_EVERY
+    }
+
+    fn update_extra_lives(&mut self, resources: &Resources, sound_queue: &mut SoundQueue) {
+        while self.player_score >= Self::extra_life_score(self.next_extra_life_index) {
+            self.next_extra_life_index += 1; 
+            self.player_lives = (self.player_lives + 1).min(PLAYER_LIVES_MAX); 
+            resources.play_sound(SoundIdentifier::WaveCleared, sound_queue, Volume(0.6f32)); 
+            let pos = self.player_pos(); 
+            self.floating_texts.spawn(String::from("1UP"), pos, GREEN); 
+        }
+    }
+
+    fn on_out_of_lives(&mut self) -> Option<GameStateCommand> {
+        if self.continues_left > 0 {
+            self.continue_timer_optional = Some(CONTINUE_TIME); 
+            return None; 
+        }
+
+        Some(GameStateCommand::ChangeState(
+            GameStateIdentifier::Menu,
+            Some(ChangeStatePayload::MenuPayload(MenuPayload {
+                score: self.player_score,
+            })),
+        ))
+    }
+
+    fn resume_from_continue(&mut self) {
+        self.continues_left -= 1; 
+        self.continue_timer_optional = None; 
+        self.player_lives = PLAYER_LIVES_START; 
+        self.player_score = (self.player_score as f32 * CONTINUE_SCORE_KEEP_FRACTION) as i32; 
+        self.next_extra_life_index = 0; 
+        while self.player_score >= Self::extra_life_score(self.next_extra_life_index) {
+            self.next_extra_life_index += 1; 
+        }
+        self.combo.reset(); 
+        self.kill_bullets(BulletHurtType::Player); 
+        systems::cull(&mut self.world, &mut self.system_buffers); 
+        self.player_mut().process_optional_command(Some(PlayerCommand::ChangeState(PlayerState::Invincible(PLAYER_TIME_INVISBLE)))); 
+    }
+
+    // flags every bullet that hurts hurt_type, returns how many
+    fn kill_bullets(&mut self, hurt_type: BulletHurtType) -> i32 {
+        let mut killed = 0;
+        for entity in self.world.entities.iter() {
+            if let Some(projectile) = self.world.projectiles.get_mut(entity) {
+                if projectile.hurt_type == hurt_type && !projectile.is_kill {
+                    projectile.is_kill = true;
+                    killed += 1;
+                }
+            }
+        }
+        killed
+    }
+
+    // clears every enemy bullet on screen, paid for with a full graze meter
+    fn graze_burst(&mut self, resources: &Resources, sound_queue: &mut SoundQueue) {
+        let bullets_cleared = self.kill_bullets(BulletHurtType::Player);
+        systems::cull(&mut self.world, &mut self.system_buffers);
+
+        let score_add = bullets_cleared * SCORE_GRAZE_BURST_BULLET;
+        self.player_score += score_add;
+        let pos = self.player_pos();
+        self.floating_texts.spawn(format!("BURST +{}", score_add), pos, SKYBLUE);
+        resources.play_sound(SoundIdentifier::WaveCleared, sound_queue, Volume(0.6f32));
+    }
+
+    fn draw_graze_meter(&self, resources: &Resources) {
+        let meter_width = 30f32;
+        let meter_x = GAME_SIZE_X as f32 - meter_width - 5f32;
+        let meter_y = GAME_SIZE_Y as f32 - resources.ground_bg.height() + 4f32;
+        let graze_meter = self.player().graze_meter;
+        let meter_full = graze_meter >= 1f32;
+        let color = if meter_full && (get_time() * 8f64) as i32 % 2 == 0 { WHITE } else { SKYBLUE };
+
+        draw_rectangle_lines(meter_x - 1f32, meter_y - 1f32, meter_width + 2f32, 5f32, 1f32, DARKGRAY);
+        draw_rectangle(meter_x, meter_y, meter_width * graze_meter, 3f32, color);
+    }
+
+    // live bullets per side against the slots the pool has, to spot leaks and bullet spam
+    fn draw_pool_debug(&self, resources: &Resources) {
+        let entities = &self.world.entities;
+        let bullets = entities.iter().filter(|entity| self.world.projectiles.get(*entity).is_some()).count();
+        let player_bullets = entities.iter()
+            .filter(|entity| self.world.projectiles.get(*entity).is_some_and(|p| p.hurt_type == BulletHurtType::Enermy))
+            .count();
+        let lines = [
+            format!("ENTITIES {}/{}", entities.live_count(), entities.capacity()),
+            format!("PLAYER {} DEMON {}", player_bullets, bullets - player_bullets),
+            format!("DEMONS {}", self.world.enermies().count())
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            draw_text_ex(
+                line,
+                2f32,
+                (DEBUG_FONT_SIZE as usize * (i + 2)) as f32,
+                TextParams {
+                    font: resources.font,
+                    font_size: DEBUG_FONT_SIZE,
+                    font_scale: 1f32,
+                    color: GREEN,
+                    font_scale_aspect: 1f32
+                }
+            );
+        }
+    }
+
+    fn draw_ground_and_player(&self, resources: &Resources) {
+        draw_texture_ex(
+            resources.ground_bg,
+            0f32,
+            GAME_SIZE_Y as f32 - resources.ground_bg.height(),
+            WHITE,
+            DrawTextureParams {
+                //dest_size: Some(vec2(screen_width(), screen_height())),
+                dest_size: Some(Vec2::new(GAME_SIZE_X as f32, resources.ground_bg.height())),
+                ..Default::default()
+            },
+        );
+
+        draw_lives(
+            &self.player_lives,
+            resources.life,
+            &resources.ground_bg,
+            &self.wave_manager,
+        );
+
+        self.draw_graze_meter(resources);
+        self.floating_texts.draw(resources.font);
+        let (transform, collider) = (self.world.transforms.get(self.player_entity).unwrap(), self.world.colliders.get(self.player_entity).unwrap()); 
+        self.player().draw(transform, collider);
+    }
+
+    // the game stays frozen behind the countdown
+    fn update_state_continue(&mut self, dt: f32, input: &GameInput) -> Option<GameStateCommand> {
+        if input.continue_pressed {
+            self.resume_from_continue(); 
+            return None; 
+        }
+
+        if let Some(continue_timer) = &mut self.continue_timer_optional {
+            *continue_timer -= dt; 
+            if *continue_timer <= 0f32 {
+                self.continues_left = 0; 
+                self.continue_timer_optional = None; 
+                return self.on_out_of_lives(); 
+            }
+        }
+        None
+    }
+
+    // counts down broods for every dead mini, returns how many were fully killed by the player
+    fn update_broods(&mut self) -> i32 {
+        for enemy in self.world.enermies().filter(|e| e.just_died()) {
+            if let Some(brood_id) = enemy.state_shared.brood_id_optional {
+                if let Some(brood) = self.broods.get_mut(&brood_id) {
+                    brood.children_left -= 1; 
+                    if !enemy.state_shared.killed_by_player {
+                        brood.broken = true; 
+                    }
+                }
+            }
+        }
+
+        let mut broods_cleared = 0; 
+        self.broods.retain(|_id, brood| {
+            if brood.children_left > 0 {
+                return true; 
+            }
+            if !brood.broken {
+                broods_cleared += 1; 
+            }
+            false
+        });
+        broods_cleared
+    }
+
+    // one frame of the game without drawing anything, all input comes in through input
+    pub fn step(&mut self, dt: f32, input: &GameInput, resources: &Resources, sound_queue: &mut SoundQueue) -> Option<GameStateCommand> {
+        if self.continue_timer_optional.is_some() {
+            return self.update_state_continue(dt, input); 
+        }
+
+        let manager_message_optional = self.wave_manager.update(dt, &mut self.world, resources, sound_queue);
+        if let Some(manager_message) = manager_message_optional {
+            match manager_message {
+                WaveManagerMessage::LevelCleared => {
+                    self.player_lives += 1;
+                    self.player_lives = self.player_lives.min(PLAYER_LIVES_MAX);
+                    let score_add = match self.wave_manager.last_enermydeath_reason {
+                        LastEnermyDeathReason::Environment => SCORE_SURVIVED_ALL,
+                        LastEnermyDeathReason::Player => SCORE_KILL_ALL,
+                    };
+                    resources.play_sound(SoundIdentifier::WaveCleared, sound_queue, Volume(0.6f32));
+                    self.player_score += score_add;
+                    self.floating_texts.spawn_ex(
+                        format!("WAVE CLEAR +{}", score_add),
+                        vec2(GAME_CENTER_X, GAME_CENTER_Y),
+                        YELLOW,
+                        FLOATING_TEXT_LIFETIME * 2f32,
+                    );
+                    self.send_attack(VERSUS_ATTACK_WAVE_CLEARED); 
+                    self.bunkers.reset(); 
+                }
+            }
+        }
+
+        self.combo.update(dt); 
+        systems::enermies(&mut self.world, &mut self.system_buffers, self.player_entity, dt, resources, &mut self.wave_manager, sound_queue); 
+        systems::movement(&mut self.world, dt); 
+        systems::animation(&mut self.world, dt); 
+        systems::colliders(&mut self.world); 
+
+        // taken out so scoring can borrow the rest of the game, put back empty to keep its capacity
+        let mut events = std::mem::take(&mut self.events); 
+        systems::bunkers(&mut self.world, &mut self.bunkers, &mut self.wave_manager); 
+        systems::shoot_down(&mut self.world, &mut self.system_buffers, &mut events); 
+        systems::hazards(&mut self.world, self.player_entity, &mut self.wave_manager, &mut events); 
+        systems::broadphase(&self.world, &mut self.bullet_hash, &mut self.enermy_hash); 
+        systems::player_damage(&mut self.world, self.player_entity, &self.bullet_hash, &self.enermy_hash, &mut self.system_buffers, &mut events); 
+        systems::enermy_damage(&mut self.world, &self.enermy_hash, &mut self.system_buffers, &mut self.wave_manager, &mut events); 
+        systems::misses(&mut self.world, &mut events); 
+        self.score(&events, resources, sound_queue); 
+        self.spawn_broods(&events, resources, sound_queue); 
+        events.clear(); 
+        self.events = events; 
+
+        let broods_cleared = self.update_broods(); 
+        if broods_cleared > 0 {
+            let score_add = broods_cleared * SCORE_BROOD_CLEARED * self.combo.multiplier(); 
+            self.player_score += score_add; 
+            let pos = self.player_pos(); 
+            self.floating_texts.spawn(format!("BROOD +{}", score_add), pos, PINK); 
+        }
+
+        systems::cull(&mut self.world, &mut self.system_buffers); 
+
+        self.update_extra_lives(resources, sound_queue); 
+        if self.player_lives <= 0 {
+            let command_optional = self.on_out_of_lives(); 
+            if command_optional.is_some() {
+                return command_optional; 
+            }
         }
 
+        self.floating_texts.update(dt); 
+        if self.continue_timer_optional.is_none() {
+            let player_message_optional = systems::player(&mut self.world, self.player_entity, dt, &input.player, resources, sound_queue); 
+            if let Some(PlayerMessage::GrazeBurst) = player_message_optional {
+                self.graze_burst(resources, sound_queue); 
+            }
+        }
+        None
+    }
+
+    // the scoring system, goes through what the collision systems found in the order it happened
+    fn score(&mut self, events: &[GameEvent], resources: &Resources, sound_queue: &mut SoundQueue) {
+        for event in events.iter() {
+            match event {
+                GameEvent::PlayerHurt => {
+                    self.player_lives -= 1; 
+                    self.combo.register_damage(); 
+                    resources.play_sound(SoundIdentifier::PlayerOuch, sound_queue, Volume(1.0f32)); 
+                }
+                GameEvent::Graze => self.player_score += SCORE_GRAZE, 
+                GameEvent::ShotDown(pos) => {
+                    self.player_score += SCORE_BULLET_SHOT_DOWN; 
+                    self.floating_texts.spawn(format!("+{}", SCORE_BULLET_SHOT_DOWN), *pos, BULLET_ORB_TINT); 
+                }
+                GameEvent::HazardKill(pos) => {
+                    self.player_score += SCORE_HAZARD_KILL; 
+                    self.floating_texts.spawn(format!("+{}", SCORE_HAZARD_KILL), *pos, ORANGE); 
+                }
+                GameEvent::Tink(pos) => self.floating_texts.spawn(String::from("TINK"), *pos, GRAY), 
+                GameEvent::Kill(pos, _, enemy_type, _) => {
+                    resources.play_sound(SoundIdentifier::EnermyOuch, sound_queue, Volume(1.0f32)); 
+                    let score_add = match enemy_type {
+                        EnermyType::NORMAL => SCORE_NORMAL,
+                        EnermyType::MINI => SCORE_MINI,
+                        EnermyType::Boss => SCORE_BOSS,
+                        EnermyType::Kamikaze => SCORE_KAMIKAZE,
+                        EnermyType::Shielded => SCORE_SHIELDED,
+                        EnermyType::Sniper => SCORE_SNIPER,
+                    }; 
+                    self.register_kill(score_add, *pos); 
+                }
+                GameEvent::Miss => self.combo.register_miss()
+            }
+        }
+    }
+
+    // the spawning system, demons killed this frame break into broods of minis
+    fn spawn_broods(&mut self, events: &[GameEvent], resources: &Resources, sound_queue: &mut SoundQueue) {
+        for event in events.iter() {
+            if let GameEvent::Kill(pos, EnermyDeathMethod::SpawnChildren(amount), _, enemy_color) = event {
+                resources.play_sound(SoundIdentifier::SpawnMini, sound_queue, Volume(1.0f32)); 
+                let brood_id = self.next_brood_id; 
+                self.next_brood_id = self.next_brood_id.wrapping_add(1); 
+                self.broods.insert(brood_id, Brood { children_left: *amount, broken: false }); 
+                systems::spawn_brood(&mut self.world, resources, *pos, *amount, *enemy_color, self.wave_manager.wave_count, brood_id); 
+            }
+        }
     }
 }
 
 
 impl GameState for GameStateGame {
-    fn draw(&self, _resources: &Resources) {}
+    fn draw(&mut self, resources: &Resources) {
+        self.bunkers.draw(); 
+        for entity in self.world.enermy_entities() {
+            let world = &self.world; 
+            let (transform, collider, animation) = (
+                world.transforms.get(entity).unwrap(), 
+                world.colliders.get(entity).unwrap(), 
+                world.animations.get(entity).unwrap()
+            ); 
+            world.enermies.get(entity).unwrap().draw(transform, collider, animation, resources); 
+        }
+        if self.debug_paths {
+            for enemy in self.world.enermies() {
+                enemy.draw_path_debug(); 
+            }
+        }
+        systems::draw(&self.world); 
+        self.wave_manager.hazards.draw(); 
+        self.draw_ground_and_player(resources); 
+        if self.debug_pool {
+            self.draw_pool_debug(resources); 
+        }
+    }
+
     fn draw_unscaled(&self, resources: &Resources) {
         let game_diff_w = screen_width() / GAME_SIZE_X as f32; 
         let game_diff_h = screen_height() / GAME_SIZE_Y as f32; 
@@ -258,180 +753,270 @@ impl GameState for GameStateGame {
                 color: YELLOW,
                 font_scale_aspect: 1f32
             }
-        )
+        );
 
+        if self.combo.chain > 0 {
+            let combo_text = format!("x{} CHAIN {}", self.combo.multiplier(), self.combo.chain); 
+            let combo_font_size = (aspect_diff * 6f32) as u16; 
+            let combo_x = width_padding + scaled_game_size_w - combo_text.len() as f32 * combo_font_size as f32 * 0.6f32 - aspect_diff * 4f32; 
+            let combo_y = height_padding + combo_font_size as f32 * 2f32; 
+            draw_text_ex(
+                combo_text.as_ref(), 
+                combo_x,
+                combo_y, 
+                TextParams {
+                    font: resources.font, 
+                    font_size: combo_font_size,
+                    font_scale: 1f32,
+                    color: ORANGE,
+                    font_scale_aspect: 1f32
+                }
+            );
 
-    }
+            // chain timer bar, shrinks until the chain drops
+            let bar_width = combo_text.len() as f32 * combo_font_size as f32 * 0.6f32; 
+            draw_rectangle(
+                combo_x, 
+                combo_y + aspect_diff * 2f32, 
+                bar_width * self.combo.timer_fraction(), 
+                aspect_diff * 1.5f32, 
+                ORANGE
+            );
+        }
 
-    fn update(&mut self, dt: f32, resources: &Resources, sound_mixer: &mut SoundMixer) -> Option<GameStateCommand>{
-        let manager_message_optional =
-        self.wave_manager
-            .update(dt, &mut self.enermies, resources, sound_mixer);
-    if let Some(manager_message) = manager_message_optional {
-        match manager_message {
-            WaveManagerMessage::LevelCleared => {
-                self.player_lives += 1;
-                self.player_lives = self.player_lives.min(PLAYER_LIVES_MAX);
-                let score_add = match self.wave_manager.last_enermydeath_reason {
-                    LastEnermyDeathReason::Environment => SCORE_SURVIVED_ALL,
-                    LastEnermyDeathReason::Player => SCORE_KILL_ALL,
-                };
-                resources.play_sound(SoundIdentifier::WaveCleared, sound_mixer, Volume(0.6f32));
-                self.player_score += score_add;
+        if let WaveManagerState::BossWarning(_) = self.wave_manager.state {
+            // flash the warning while the boss is on its way
+            if (get_time() * 4f64) as i32 % 2 == 0 {
+                let warning_text = "WARNING";
+                let mut text_x = width_padding + scaled_game_size_w * 0.5f32;
+                text_x -= warning_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
+                draw_text_ex(
+                    warning_text, 
+                    text_x,
+                    screen_height() * 0.5f32, 
+                    TextParams {
+                        font: resources.font, 
+                        font_size,
+                        font_scale: 1f32,
+                        color: RED,
+                        font_scale_aspect: 1f32
+                    }
+                );
             }
         }
+
+        let boss_optional = self.world.enermies().find(|e| matches!(e.state_shared.enermy_type, EnermyType::Boss)); 
+        if let Some(boss) = boss_optional {
+            let bar_width = scaled_game_size_w * 0.6f32; 
+            let bar_height = aspect_diff * 2f32; 
+            let bar_x = width_padding + (scaled_game_size_w - bar_width) * 0.5f32; 
+            let bar_y = height_padding + font_size as f32 * 2.6f32; 
+            let health_fraction = boss.state_shared.health.max(0) as f32 / boss.state_shared.max_health as f32; 
+            draw_rectangle(bar_x, bar_y, bar_width, bar_height, DARKGRAY); 
+            draw_rectangle(bar_x, bar_y, bar_width * health_fraction, bar_height, RED); 
+        }
+
+        if let Some(continue_timer) = self.continue_timer_optional {
+            let continue_texts = [
+                format!("CONTINUE? {}", continue_timer.ceil() as i32), 
+                String::from("PRESS ENTER"), 
+                format!("CREDITS {}", self.continues_left)
+            ]; 
+            for (i, continue_text) in continue_texts.iter().enumerate() {
+                let mut text_x = width_padding + scaled_game_size_w * 0.5f32;
+                text_x -= continue_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
+                draw_text_ex(
+                    continue_text.as_ref(), 
+                    text_x,
+                    screen_height() * 0.5f32 + i as f32 * font_size as f32 * 1.5f32, 
+                    TextParams {
+                        font: resources.font, 
+                        font_size,
+                        font_scale: 1f32,
+                        color: YELLOW,
+                        font_scale_aspect: 1f32
+                    }
+                );
+            }
+        }
+
+
     }
 
-    for enemy in self.enermies.iter_mut() {
-        enemy.update(
-            dt,
-            &mut self.bullet,
-            resources,
-            &self.player.pos,
-            &mut self.wave_manager,
-            sound_mixer,
-        );
-        enemy.draw();
+    fn update(&mut self, dt: f32, resources: &Resources, sound_queue: &mut SoundQueue) -> Option<GameStateCommand>{
+        if is_key_pressed(KEY_DEBUG_PATHS) {
+            self.debug_paths = !self.debug_paths;
+        }
+        if is_key_pressed(KEY_DEBUG_POOL) {
+            self.debug_pool = !self.debug_pool;
+        }
+
+        let input = GameInput {
+            player: self.controls.read(), 
+            continue_pressed: is_key_pressed(KEY_CONTINUE)
+        }; 
+        self.step(dt, &input, resources, sound_queue)
     }
 
-    for bullet in self.bullet.iter_mut() {
-        bullet.update(dt);
-        bullet.draw();
+    fn on_enter(&mut self, resources: &Resources, payload_optional: Option<ChangeStatePayload>) {
+        self.wave_manager.reset(); 
+        self.world.clear(); 
+        self.player_entity = Self::spawn_player(&mut self.world, resources); 
+        let transform = self.world.transforms.get_mut(self.player_entity).unwrap(); 
+        self.world.players.get_mut(self.player_entity).unwrap().reset(transform, resources); 
+        self.player_score = 0; 
+        self.player_lives = PLAYER_LIVES_START; 
+        self.outgoing_attacks.clear(); 
+        self.combo.reset(); 
+        self.broods.clear(); 
+        self.floating_texts.clear(); 
+        self.next_extra_life_index = 0; 
+        self.continues_left = self.continues_max; 
+        self.continue_timer_optional = None; 
+        self.bunkers.reset(); 
     }
+}
 
-    // bullets hurting player
-    for bullet in self.bullet.iter_mut().filter(|b| b.hurt_type == BulletHurtType::Player){
-        if bullet.overlaps(&self.player.collision_rect) {
-            if self.player.state != PlayerState::Normal {
-                continue;
-            }
-            self.player_lives -= 1;
-            resources.play_sound(SoundIdentifier::PlayerOuch, sound_mixer, Volume(1.0f32));
-            // CHANGE PLAYER STATE
-            self.player.process_optional_command(Some(PlayerCommand::ChangeState(PlayerState::Invincible(PLAYER_TIME_INVISBLE),)));
-            if self.player_lives <= 0 {
-                return Some(GameStateCommand::ChangeState(
-                    GameStateIdentifier::Menu,
-                    Some(ChangeStatePayload::MenuPayload(MenuPayload {
-                        score: self.player_score,
-                    })),
-                ));
-            }
-            bullet.is_kill = true;
-            break;
-        }
-    }
-
-    // homing enemies hurting player
-    for enemy in self.enermies.iter_mut().filter(|e| variant_eq(&e.state, &EnermyState::Homing(EnermyStateHoming {})))
-        // filter enemies containing homing state, variant_eq is used so we can disregard homing data
-        
-    {
-        if enemy.overlaps(&self.player.collision_rect) {
-            let player_invisible =
-                variant_eq(&self.player.state, &PlayerState::Invincible(0f32));
-            if !player_invisible {
-                self.player_lives -= 1;
-                resources.play_sound(SoundIdentifier::PlayerOuch, sound_mixer, Volume(1.0f32));
-                self.player
-                    .process_optional_command(Some(PlayerCommand::ChangeState(
-                        PlayerState::Invincible(PLAYER_TIME_INVISBLE),
-                    )));
-                enemy.state_shared.health = 0;
-            }
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VersusSide {
+    Left, 
+    Right
+}
+
+
+// two independent games side by side, each rendered into its own target
+pub struct GameStateVersus {
+    games: [GameStateGame; 2], 
+    render_targets: [RenderTarget; 2]
+}
+
+
+impl GameStateVersus {
+    pub fn new(resources: &Resources) -> Self {
+        let mut game_left = GameStateGame::new(resources, PlayerControls::versus_left()); 
+        let mut game_right = GameStateGame::new(resources, PlayerControls::versus_right()); 
+        // last player alive wins, so no continuing
+        for game in [&mut game_left, &mut game_right] {
+            game.attacks_enabled = true; 
+            game.continues_max = 0; 
+            game.continues_left = 0; 
+        }
+
+        let render_targets = [
+            render_target(GAME_SIZE_X as u32, GAME_SIZE_Y as u32), 
+            render_target(GAME_SIZE_X as u32, GAME_SIZE_Y as u32)
+        ]; 
+        for render_target in render_targets.iter() {
+            render_target.texture.set_filter(FilterMode::Nearest); 
+        }
+
+        GameStateVersus {
+            games: [game_left, game_right], 
+            render_targets
         }
     }
+}
 
-    // todo explain
-    let mut death_methods = Vec::<(Vec2, EnermyDeathMethod, EnermyType, EnermyColor)>::with_capacity(4);
 
-    // bullets hurting enemies
-    for bullet in self.bullet.iter_mut().filter(|b| b.hurt_type == BulletHurtType::Enermy){
-        for enemy in self.enermies.iter_mut() {
-            if enemy.overlaps(&bullet.collision_rect) && !bullet.is_kill {
-                enemy.state_shared.health -= 1;
-                self.wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Player;
-                // death
-                if enemy.state_shared.health <= 0 {
-                    resources.play_sound(
-                        SoundIdentifier::EnermyOuch,
-                        sound_mixer,
-                        Volume(1.0f32),
-                    );
-                    death_methods.push((
-                        enemy.state_shared.pos,
-                        enemy.state_shared.death_method,
-                        enemy.state_shared.enermy_type,
-                        enemy.state_shared.enermy_color,
-                    ));
-                }
-                // can only hurt one enemy, flag for deletion
-                bullet.is_kill = true;
-            }
+impl GameState for GameStateVersus {
+    fn draw(&mut self, resources: &Resources) {
+        // each game renders into its own target first, then both get drawn side by side
+        push_camera_state(); 
+        for (game, render_target) in self.games.iter_mut().zip(self.render_targets.iter()) {
+            set_camera(&game_camera(*render_target)); 
+            clear_background(BLACK); 
+            game.draw(resources); 
         }
+        pop_camera_state(); 
+
+        let view_size = vec2(GAME_CENTER_X, GAME_CENTER_Y); 
+        for (i, render_target) in self.render_targets.iter().enumerate() {
+            draw_texture_ex(
+                render_target.texture,
+                i as f32 * GAME_CENTER_X,
+                GAME_CENTER_Y * 0.5f32,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(view_size),
+                    ..Default::default()
+                },
+            );
+        }
+
+        draw_line(GAME_CENTER_X, 0f32, GAME_CENTER_X, GAME_SIZE_Y as f32, 1f32, DARKGRAY); 
     }
 
-    for (pos, death_method, enemy_type, enemy_color) in death_methods.iter() {
-        let score_add = match enemy_type {
-            EnermyType::NORMAL => SCORE_NORMAL,
-            EnermyType::MINI => SCORE_MINI,
-        };
-        self.player_score += score_add;
-        match death_method {
-            EnermyDeathMethod::None => {}
-            EnermyDeathMethod::SpawnChildren(amount) => {
-                resources.play_sound(SoundIdentifier::SpawnMini, sound_mixer, Volume(1.0f32));
-                let spawn_width = 20f32;
-                let step = 1. / (*amount as f32);
-                for i in 0..*amount {
-                    let spawn_pos = *pos + vec2(step * spawn_width * i as f32, 0f32);
-                    spawn_enermy(
-                        &mut self.enermies,
-                        resources,
-                        SpawnBlueprint::Mini(spawn_pos),
-                        *enemy_color,
-                    );
+    fn draw_unscaled(&self, resources: &Resources) {
+        let game_diff_w = screen_width() / GAME_SIZE_X as f32; 
+        let game_diff_h = screen_height() / GAME_SIZE_Y as f32; 
+        let aspect_diff = game_diff_w.min(game_diff_h); 
+
+        let scaled_game_size_w = GAME_SIZE_X as f32 * aspect_diff; 
+        let scaled_game_size_h = GAME_SIZE_Y as f32 * aspect_diff;
+
+        let width_padding = (screen_width() - scaled_game_size_w) * 0.5f32;
+        let height_padding = (screen_height() - scaled_game_size_h) * 0.5f32;
+
+        let font_size = (aspect_diff * 8f32) as u16;
+        for (i, game) in self.games.iter().enumerate() {
+            let score_text = format!("P{} {}", i + 1, game.player_score); 
+            let mut text_x = width_padding + scaled_game_size_w * (0.25f32 + 0.5f32 * i as f32);
+            text_x -= score_text.len() as f32 * 0.5f32 * font_size as f32 * 0.6f32;
+            draw_text_ex(
+                score_text.as_ref(), 
+                text_x,
+                height_padding + font_size as f32 * 2f32, 
+                TextParams {
+                    font: resources.font, 
+                    font_size,
+                    font_scale: 1f32,
+                    color: YELLOW,
+                    font_scale_aspect: 1f32
                 }
-            }
+            );
         }
     }
 
-    
-    self.bullet.retain(|e| !e.is_kill);// remove bullets that hit something
-    self.enermies.retain(|e| e.state_shared.health > 0); // remove dead enemies
-
-    draw_texture_ex(
-        resources.ground_bg,
-        0f32,
-        GAME_SIZE_Y as f32 - resources.ground_bg.height(),
-        WHITE,
-        DrawTextureParams {
-            //dest_size: Some(vec2(screen_width(), screen_height())),
-            dest_size: Some(Vec2::new(GAME_SIZE_X as f32, resources.ground_bg.height())),
-            ..Default::default()
-        },
-    );
+    fn update(&mut self, dt: f32, resources: &Resources, sound_queue: &mut SoundQueue) -> Option<GameStateCommand> {
+        let mut lost = [false, false]; 
+        for (i, game) in self.games.iter_mut().enumerate() {
+            lost[i] = game.update(dt, resources, sound_queue).is_some(); 
+        }
 
-        draw_lives(
-            &self.player_lives,
-            resources.life,
-            &resources.ground_bg,
-            &self.wave_manager,
-        );
+        let attacks_from_left = self.games[0].take_outgoing_attacks(); 
+        let attacks_from_right = self.games[1].take_outgoing_attacks(); 
+        for spawn_blueprint in attacks_from_left {
+            self.games[1].wave_manager.queue_spawn(spawn_blueprint); 
+        }
+        for spawn_blueprint in attacks_from_right {
+            self.games[0].wave_manager.queue_spawn(spawn_blueprint); 
+        }
 
-        self.player.update(dt, &mut self.bullet, resources, sound_mixer);
-        self.player.draw();
+        let winner_optional = match lost {
+            [false, false] => None, 
+            [true, false] => Some(VersusSide::Right), 
+            [false, true] => Some(VersusSide::Left), 
+            [true, true] => if self.games[0].player_score >= self.games[1].player_score {
+                Some(VersusSide::Left)
+            } else {
+                Some(VersusSide::Right)
+            }
+        }; 
+
+        if let Some(winner) = winner_optional {
+            return Some(GameStateCommand::ChangeState(
+                GameStateIdentifier::Menu, 
+                Some(ChangeStatePayload::VersusPayload(VersusPayload { winner })),
+            ));
+        }
         None
-           
     }
 
-    fn on_enter(&mut self, resources: &Resources, payload_optional: Option<ChangeStatePayload>) {
-        self.wave_manager.reset(); 
-        self.player.reset(resources); 
-        self.player_score = 0; 
-        self.player_lives = PLAYER_LIVES_START; 
-        self.enermies.clear(); 
-        self.bullet.clear(); 
+    fn on_enter(&mut self, resources: &Resources, _payload_optional: Option<ChangeStatePayload>) {
+        for game in self.games.iter_mut() {
+            game.on_enter(resources, None); 
+        }
     }
 }
 
@@ -440,7 +1025,8 @@ pub struct GameManager {
     states: HashMap<GameStateIdentifier, Box<dyn GameState>>,
     current_state_identifier: GameStateIdentifier,
     resources: Resources, 
-    sound_mixer: SoundMixer
+    sound_mixer: SoundMixer, 
+    sound_queue: SoundQueue
 }
 
 
@@ -460,11 +1046,13 @@ impl GameManager {
             states, 
             current_state_identifier: GameStateIdentifier::Menu,
             resources, 
-            sound_mixer
+            sound_mixer, 
+            sound_queue: SoundQueue::new()
         }
     }
 
     pub fn frame_sound(&mut self) {
+        self.resources.play_queued_sounds(&mut self.sound_queue, &mut self.sound_mixer); 
         self.sound_mixer.frame(); 
     }
 
@@ -472,7 +1060,7 @@ impl GameManager {
     pub fn update(&mut self, dt: f32){
         let state_command_optional = 
             if let Some(game_state) = self.states.get_mut(&self.current_state_identifier){
-                game_state.update(dt, &self.resources, &mut self.sound_mixer) 
+                game_state.update(dt, &self.resources, &mut self.sound_queue) 
             }else {
                 None
             };
@@ -490,8 +1078,8 @@ impl GameManager {
 
     }
 
-    pub fn draw(&self) {
-        if let Some(game_state) = self.states.get(&self.current_state_identifier) {
+    pub fn draw(&mut self) {
+        if let Some(game_state) = self.states.get_mut(&self.current_state_identifier) {
             game_state.draw(&self.resources); 
         }
     }
@@ -501,4 +1089,181 @@ impl GameManager {
             game_state.draw_unscaled(&self.resources); // the scaled proportion 
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::load_resources_headless;
+
+    const REPLAY_SEED: u64 = 1234;
+    const REPLAY_FRAMES: usize = 3600;
+    const REPLAY_DT: f32 = 1f32 / 60f32;
+    const REPLAY_GOLDEN: &str = "assets/replays/seed_1234.replay";
+
+    // sweeps right and taps left to snap back to the edge, firing in bursts, spending the graze meter and taking every continue
+    fn scripted_input(frame: usize) -> GameInput {
+        let sweep = frame % 240;
+        GameInput {
+            player: PlayerInput {
+                left: sweep == 200,
+                right: sweep < 120,
+                shoot: frame % 100 < 80,
+                special: frame % 150 == 75
+            },
+            continue_pressed: true
+        }
+    }
+
+    // the bits of every float, so even a rounding change shows up
+    fn hash_bits(hash: &mut u64, values: &[u32]) {
+        for value in values.iter() {
+            for byte in value.to_le_bytes().iter() {
+                *hash = (*hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+
+    // one line per frame, score, lives, where the player stands, how many demons and bullets are around,
+    // and a hash over every demon and bullet position
+    fn snapshot(game: &GameStateGame) -> String {
+        let mut hash = 0xcbf29ce484222325u64;
+        for entity in game.world.enermy_entities() {
+            let pos = game.world.transforms.get(entity).unwrap().pos;
+            let health = game.world.enermies.get(entity).unwrap().state_shared.health;
+            hash_bits(&mut hash, &[pos.x.to_bits(), pos.y.to_bits(), health as u32]);
+        }
+        // bullets by position, which slot a bullet got doesn't change how the game plays
+        let mut bullets: Vec<[u32; 2]> = game.world.entities.iter()
+            .filter(|entity| game.world.projectiles.get(*entity).is_some())
+            .filter_map(|entity| game.world.transforms.get(entity))
+            .map(|transform| [transform.pos.x.to_bits(), transform.pos.y.to_bits()])
+            .collect();
+        bullets.sort_unstable();
+        for bullet in bullets.iter() {
+            hash_bits(&mut hash, bullet);
+        }
+        format!(
+            "{} {} {:.3} {} {} {:016x}",
+            game.player_score,
+            game.player_lives,
+            game.player_pos().x,
+            game.world.enermies().count(),
+            bullets.len(),
+            hash
+        )
+    }
+
+    fn replay(seed: u64, frames: usize) -> Vec<String> {
+        rand::srand(seed);
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut game = GameStateGame::new(&resources, PlayerControls::single());
+        game.on_enter(&resources, None);
+
+        let mut trace = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            let command_optional = game.step(REPLAY_DT, &scripted_input(frame), &resources, &mut sound_queue);
+            trace.push(snapshot(&game));
+            if command_optional.is_some() {
+                trace.push(String::from("game over"));
+                break;
+            }
+        }
+        trace
+    }
+
+    fn golden_trace() -> Vec<String> {
+        std::fs::read_to_string(REPLAY_GOLDEN).unwrap()
+            .lines()
+            .filter(|line| !line.starts_with(';'))
+            .map(String::from)
+            .collect()
+    }
+
+    fn assert_same_frames(trace: &[String], expected: &[String]) {
+        for (frame, (line, expected_line)) in trace.iter().zip(expected.iter()).enumerate() {
+            assert_eq!(line, expected_line, "replay went different at frame {}", frame);
+        }
+        assert_eq!(trace.len(), expected.len(), "replay ran for a different number of frames");
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let trace = replay(REPLAY_SEED, REPLAY_FRAMES);
+        assert_same_frames(&replay(REPLAY_SEED, REPLAY_FRAMES), &trace);
+    }
+
+    #[test]
+    fn replay_matches_golden() {
+        assert_same_frames(&replay(REPLAY_SEED, REPLAY_FRAMES), &golden_trace());
+    }
+
+    #[test]
+    fn extra_lives_come_at_each_threshold_up_to_the_cap() {
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut game = GameStateGame::new(&resources, PlayerControls::single());
+
+        game.player_score = SCORE_EXTRA_LIFE_THRESHOLDS[0] - 1;
+        game.update_extra_lives(&resources, &mut sound_queue);
+        assert_eq!(game.player_lives, PLAYER_LIVES_START);
+
+        game.player_score = SCORE_EXTRA_LIFE_THRESHOLDS[0];
+        game.update_extra_lives(&resources, &mut sound_queue);
+        assert_eq!(game.player_lives, PLAYER_LIVES_START + 1);
+
+        // skipping past the rest of the thresholds at once still pays out each of them, but never over the cap
+        game.player_score = SCORE_EXTRA_LIFE_THRESHOLDS[2];
+        game.update_extra_lives(&resources, &mut sound_queue);
+        assert_eq!(game.player_lives, PLAYER_LIVES_MAX);
+        assert_eq!(game.next_extra_life_index, 3);
+        assert_eq!(GameStateGame::extra_life_score(3), SCORE_EXTRA_LIFE_THRESHOLDS[2] + SCORE_EXTRA_LIFE_EVERY);
+    }
+
+    #[test]
+    fn continue_restores_lives_until_the_credits_run_out() {
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut game = GameStateGame::new(&resources, PlayerControls::single());
+        let waiting = GameInput::default();
+        let pressed = GameInput { continue_pressed: true, ..GameInput::default() };
+
+        game.player_score = 1000;
+        game.player_lives = 0;
+        assert!(game.on_out_of_lives().is_none());
+        // the game stays frozen while the countdown runs
+        assert!(game.step(CONTINUE_TIME * 0.5f32, &waiting, &resources, &mut sound_queue).is_none());
+        assert_eq!(game.player_lives, 0);
+
+        assert!(game.step(REPLAY_DT, &pressed, &resources, &mut sound_queue).is_none());
+        assert!(game.continue_timer_optional.is_none());
+        assert_eq!(game.player_lives, PLAYER_LIVES_START);
+        assert_eq!(game.continues_left, CONTINUES_MAX - 1);
+        assert_eq!(game.player_score, (1000f32 * CONTINUE_SCORE_KEEP_FRACTION) as i32);
+
+        // letting the countdown run out ends the game even with credits left
+        game.player_score = 2000;
+        game.player_lives = 0;
+        assert!(game.on_out_of_lives().is_none());
+        let command_optional = game.step(CONTINUE_TIME + REPLAY_DT, &waiting, &resources, &mut sound_queue);
+        assert!(matches!(
+            command_optional,
+            Some(GameStateCommand::ChangeState(GameStateIdentifier::Menu, Some(ChangeStatePayload::MenuPayload(MenuPayload { score: 2000 }))))
+        ));
+        assert_eq!(game.continues_left, 0);
+    }
+
+    // cargo test record_replay -- --ignored, only after a change that is meant to play differently
+    #[test]
+    #[ignore]
+    fn record_replay() {
+        let header = format!(
+            "; seed {}, {} frames at {}s, see game::tests\n; score lives player_x demons bullets positions_hash\n",
+            REPLAY_SEED, REPLAY_FRAMES, REPLAY_DT
+        );
+        let trace = replay(REPLAY_SEED, REPLAY_FRAMES);
+        std::fs::create_dir_all("assets/replays").unwrap();
+        std::fs::write(REPLAY_GOLDEN, header + &trace.join("\n") + "\n").unwrap();
+    }
+}
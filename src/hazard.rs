@@ -0,0 +1,348 @@
+use macroquad::prelude::*;
+use quad_snd::mixer::Volume;
+
+use crate::{
+    constants::*,
+    resources::{ Resources, SoundIdentifier, SoundQueue }
+};
+
+
+// a meteor or a chunk of debris, falls until it leaves the screen or hits something
+pub struct Rock {
+    pos: Vec2,
+    vel: Vec2,
+    gravity: f32,
+    radius: f32,
+    color: Color,
+    is_kill: bool
+}
+
+
+impl Rock {
+    fn overlaps(&self, rect: &Rect) -> bool {
+        let closest = vec2(
+            self.pos.x.max(rect.x).min(rect.x + rect.w),
+            self.pos.y.max(rect.y).min(rect.y + rect.h)
+        );
+        closest.distance(self.pos) <= self.radius
+    }
+}
+
+
+pub struct HazardMeteorShower {
+    warning_timer: f32,
+    timer: f32,
+    spawn_timer: f32,
+    dir: f32 // meteors slant towards this side
+}
+
+
+pub struct HazardDebris {
+    warning_timer: f32,
+    columns: Vec<f32> // x of every chunk, marked during the warning
+}
+
+
+pub struct HazardLaser {
+    warning_timer: f32,
+    x: f32,
+    dir: f32
+}
+
+
+// every event warns first, then goes off
+pub enum HazardEvent {
+    MeteorShower(HazardMeteorShower),
+    Debris(HazardDebris),
+    Laser(HazardLaser)
+}
+
+
+impl HazardEvent {
+    fn random() -> Self {
+        let dir = if rand::gen_range(0f32, 1f32) < 0.5f32 { -1f32 } else { 1f32 };
+        match rand::gen_range(0, 3) {
+            0 => HazardEvent::MeteorShower(HazardMeteorShower {
+                warning_timer: HAZARD_WARNING_TIME,
+                timer: HAZARD_METEOR_TIME,
+                spawn_timer: 0f32,
+                dir
+            }),
+            1 => HazardEvent::Debris(HazardDebris {
+                warning_timer: HAZARD_WARNING_TIME,
+                columns: (0..HAZARD_DEBRIS_COUNT).map(|_| rand::gen_range(8f32, GAME_SIZE_X as f32 - 8f32)).collect()
+            }),
+            _ => HazardEvent::Laser(HazardLaser {
+                warning_timer: HAZARD_WARNING_TIME,
+                // sweeps in from the side it starts on
+                x: if dir > 0f32 { 0f32 } else { GAME_SIZE_X as f32 },
+                dir
+            })
+        }
+    }
+
+    fn warning_timer(&mut self) -> &mut f32 {
+        match self {
+            HazardEvent::MeteorShower(event) => &mut event.warning_timer,
+            HazardEvent::Debris(event) => &mut event.warning_timer,
+            HazardEvent::Laser(event) => &mut event.warning_timer
+        }
+    }
+
+    fn is_warning(&self) -> bool {
+        match self {
+            HazardEvent::MeteorShower(event) => event.warning_timer > 0f32,
+            HazardEvent::Debris(event) => event.warning_timer > 0f32,
+            HazardEvent::Laser(event) => event.warning_timer > 0f32
+        }
+    }
+
+    // true once the event is over, rocks it dropped keep falling on their own
+    fn update(&mut self, dt: f32, rocks: &mut Vec<Rock>) -> bool {
+        if self.is_warning() {
+            *self.warning_timer() -= dt;
+            return false;
+        }
+
+        match self {
+            HazardEvent::MeteorShower(event) => {
+                event.timer -= dt;
+                event.spawn_timer -= dt;
+                while event.spawn_timer <= 0f32 {
+                    event.spawn_timer += HAZARD_METEOR_SPAWN_TIME;
+                    // start further up the slant so the whole screen gets covered
+                    let x = rand::gen_range(0f32, GAME_SIZE_X as f32) - event.dir * GAME_SIZE_Y as f32 * HAZARD_METEOR_SLANT;
+                    rocks.push(Rock {
+                        pos: vec2(x, -HAZARD_METEOR_RADIUS),
+                        vel: vec2(event.dir * HAZARD_METEOR_SLANT, 1f32).normalize() * HAZARD_METEOR_SPEED,
+                        gravity: 0f32,
+                        radius: HAZARD_METEOR_RADIUS,
+                        color: ORANGE,
+                        is_kill: false
+                    });
+                }
+                event.timer <= 0f32
+            }
+            HazardEvent::Debris(event) => {
+                for x in event.columns.iter() {
+                    rocks.push(Rock {
+                        pos: vec2(*x, -HAZARD_DEBRIS_RADIUS),
+                        vel: vec2(0f32, HAZARD_DEBRIS_SPEED),
+                        gravity: HAZARD_DEBRIS_GRAVITY,
+                        radius: HAZARD_DEBRIS_RADIUS,
+                        color: GRAY,
+                        is_kill: false
+                    });
+                }
+                true
+            }
+            HazardEvent::Laser(event) => {
+                event.x += event.dir * HAZARD_LASER_SPEED * dt;
+                event.x < -HAZARD_LASER_WIDTH || event.x > GAME_SIZE_X as f32 + HAZARD_LASER_WIDTH
+            }
+        }
+    }
+
+    fn beam_optional(&self) -> Option<Rect> {
+        match self {
+            HazardEvent::Laser(event) if event.warning_timer <= 0f32 => {
+                Some(Rect::new(event.x - HAZARD_LASER_WIDTH * 0.5f32, 0f32, HAZARD_LASER_WIDTH, GAME_SIZE_Y as f32))
+            }
+            _ => None
+        }
+    }
+
+    fn draw(&self) {
+        if self.is_warning() {
+            // flashing markers where things are about to come from
+            if (get_time() * 6f64) as i32 % 2 == 0 {
+                return;
+            }
+            match self {
+                HazardEvent::MeteorShower(event) => {
+                    for i in 0..8 {
+                        let x = GAME_SIZE_X as f32 * (i as f32 + 0.5f32) / 8f32;
+                        Self::draw_marker(x, event.dir);
+                    }
+                }
+                HazardEvent::Debris(event) => {
+                    for x in event.columns.iter() {
+                        Self::draw_marker(*x, 0f32);
+                    }
+                }
+                HazardEvent::Laser(event) => {
+                    let x = event.x + event.dir * HAZARD_LASER_WIDTH;
+                    draw_line(x, 0f32, x, GAME_SIZE_Y as f32, 0.5f32, RED);
+                }
+            }
+            return;
+        }
+
+        if let Some(beam) = self.beam_optional() {
+            draw_rectangle(beam.x, beam.y, beam.w, beam.h, Color::new(1f32, 0.2f32, 0.2f32, 0.8f32));
+            draw_rectangle(beam.x + beam.w * 0.3f32, beam.y, beam.w * 0.4f32, beam.h, WHITE);
+        }
+    }
+
+    // small arrow at the top of the screen, leaning the way the danger moves
+    fn draw_marker(x: f32, lean: f32) {
+        let tip = vec2(x + lean * 2f32, 6f32);
+        draw_triangle(vec2(x - 2f32, 1f32), vec2(x + 2f32, 1f32), tip, RED);
+    }
+}
+
+
+// meteor showers, falling debris and sweeping lasers that hurt the player and demons alike
+pub struct HazardManager {
+    event_timer: f32, // counts down to the next event
+    events: Vec<HazardEvent>,
+    rocks: Vec<Rock>
+}
+
+
+impl HazardManager {
+    pub fn new() -> Self {
+        HazardManager {
+            event_timer: Self::roll_event_time(),
+            events: Vec::<HazardEvent>::new(),
+            rocks: Vec::<Rock>::new()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn roll_event_time() -> f32 {
+        rand::gen_range(HAZARD_EVENT_TIME_RANGE.x, HAZARD_EVENT_TIME_RANGE.y)
+    }
+
+    // can_start holds back new events, running ones still play out
+    pub fn update(&mut self, dt: f32, can_start: bool, resources: &Resources, sound_queue: &mut SoundQueue) {
+        if can_start && self.events.is_empty() {
+            self.event_timer -= dt;
+            if self.event_timer <= 0f32 {
+                self.event_timer = Self::roll_event_time();
+                self.events.push(HazardEvent::random());
+                resources.play_sound(SoundIdentifier::Warning, sound_queue, Volume(0.8f32));
+            }
+        }
+
+        let rocks = &mut self.rocks;
+        let mut finished = Vec::<usize>::new();
+        for (i, event) in self.events.iter_mut().enumerate() {
+            if event.update(dt, rocks) {
+                finished.push(i);
+            }
+        }
+        for i in finished.into_iter().rev() {
+            self.events.remove(i);
+        }
+
+        for rock in self.rocks.iter_mut() {
+            rock.vel.y += rock.gravity * dt;
+            rock.pos += rock.vel * dt;
+            if rock.pos.y - rock.radius > GAME_SIZE_Y as f32 {
+                rock.is_kill = true;
+            }
+        }
+        self.rocks.retain(|rock| !rock.is_kill);
+    }
+
+    // true if a rock or a laser beam touches rect, rocks break on whatever they hit
+    pub fn hit(&mut self, rect: &Rect) -> bool {
+        let mut hit = self.events.iter().filter_map(|event| event.beam_optional()).any(|beam| beam.overlaps(rect));
+        for rock in self.rocks.iter_mut().filter(|rock| !rock.is_kill) {
+            if rock.overlaps(rect) {
+                rock.is_kill = true;
+                hit = true;
+            }
+        }
+        hit
+    }
+
+    pub fn draw(&self) {
+        for event in self.events.iter() {
+            event.draw();
+        }
+        for rock in self.rocks.iter().filter(|rock| !rock.is_kill) {
+            // short streak behind every rock
+            let tail = rock.pos - rock.vel.normalize() * rock.radius * 2.5f32;
+            draw_line(tail.x, tail.y, rock.pos.x, rock.pos.y, rock.radius, Color::new(rock.color.r, rock.color.g, rock.color.b, 0.4f32));
+            draw_circle(rock.pos.x, rock.pos.y, rock.radius, rock.color);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::load_resources_headless;
+
+    fn with_event(event: HazardEvent) -> HazardManager {
+        let mut hazards = HazardManager::new();
+        hazards.events.push(event);
+        hazards
+    }
+
+    #[test]
+    fn laser_only_hurts_once_the_warning_is_over() {
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut hazards = with_event(HazardEvent::Laser(HazardLaser { warning_timer: HAZARD_WARNING_TIME, x: 0f32, dir: 1f32 }));
+        let left_edge = Rect::new(0f32, 100f32, 8f32, 8f32);
+
+        hazards.update(HAZARD_WARNING_TIME * 0.5f32, false, &resources, &mut sound_queue);
+        assert!(!hazards.hit(&left_edge));
+        hazards.update(HAZARD_WARNING_TIME * 0.5f32, false, &resources, &mut sound_queue);
+        assert!(hazards.hit(&left_edge));
+        // the beam keeps hurting while it sweeps, it doesn't break like a rock
+        assert!(hazards.hit(&left_edge));
+
+        hazards.update(1f32, false, &resources, &mut sound_queue);
+        assert!(!hazards.hit(&left_edge));
+        assert!(hazards.hit(&Rect::new(HAZARD_LASER_SPEED - 4f32, 100f32, 8f32, 8f32)));
+
+        // gone once it sweeps off the far side
+        hazards.update(GAME_SIZE_X as f32 / HAZARD_LASER_SPEED, false, &resources, &mut sound_queue);
+        assert!(hazards.events.is_empty());
+        assert!(!hazards.hit(&Rect::new(0f32, 0f32, GAME_SIZE_X as f32, GAME_SIZE_Y as f32)));
+    }
+
+    #[test]
+    fn debris_falls_after_the_warning_and_breaks_on_the_first_hit() {
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut hazards = with_event(HazardEvent::Debris(HazardDebris { warning_timer: HAZARD_WARNING_TIME, columns: vec![50f32] }));
+        let whole_screen = Rect::new(0f32, 0f32, GAME_SIZE_X as f32, GAME_SIZE_Y as f32);
+
+        hazards.update(HAZARD_WARNING_TIME * 0.5f32, false, &resources, &mut sound_queue);
+        assert!(hazards.rocks.is_empty());
+        assert!(!hazards.hit(&whole_screen));
+
+        hazards.update(HAZARD_WARNING_TIME * 0.5f32, false, &resources, &mut sound_queue);
+        hazards.update(1f32 / 60f32, false, &resources, &mut sound_queue);
+        assert_eq!(hazards.rocks.len(), 1);
+        assert!(hazards.events.is_empty());
+        assert!(!hazards.hit(&Rect::new(60f32, 0f32, 8f32, 8f32)));
+        assert!(hazards.hit(&Rect::new(46f32, 0f32, 8f32, 8f32)));
+        assert!(!hazards.hit(&whole_screen));
+    }
+
+    #[test]
+    fn new_events_wait_for_can_start() {
+        let resources = load_resources_headless();
+        let mut sound_queue = SoundQueue::new();
+        let mut hazards = HazardManager::new();
+        let event_time = hazards.event_timer;
+
+        hazards.update(event_time + 1f32, false, &resources, &mut sound_queue);
+        assert!(hazards.events.is_empty());
+        hazards.update(event_time - 0.1f32, true, &resources, &mut sound_queue);
+        assert!(hazards.events.is_empty());
+        hazards.update(0.2f32, true, &resources, &mut sound_queue);
+        assert_eq!(hazards.events.len(), 1);
+        assert!(hazards.events[0].is_warning());
+    }
+}
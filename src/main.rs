@@ -1,7 +1,7 @@
 
 use macroquad::prelude::*;
 use quad_snd::mixer::SoundMixer; 
-use game::{ GameManager, GameStateMenu, GameStateIdentifier, GameStateGame, GameState};
+use game::{ GameManager, GameStateMenu, GameStateIdentifier, GameStateGame, GameStateVersus, GameState};
 
 
 use resources::load_resouces; 
@@ -20,6 +20,23 @@ mod game;
 mod player; 
 mod wave; 
 mod bullet; 
+mod combo; 
+mod floating_text; 
+mod boss; 
+mod sexpr; 
+mod bullet_pattern; 
+mod enermy_profile; 
+mod formation; 
+mod spline; 
+mod flocking;
+mod behaviour;
+mod bunker;
+mod hazard;
+mod spatial_hash; 
+mod sprite_mask;
+mod sweep;
+mod ecs;
+mod systems;
 
 
 fn window_conf() -> Conf {
@@ -32,6 +49,21 @@ fn window_conf() -> Conf {
 }
 
 
+// camera that renders the 240x130 game world into the given render target
+pub fn game_camera(render_target: RenderTarget) -> Camera2D {
+    Camera2D {
+        // I have no idea why the zoom is this way lmao
+        zoom: vec2(1. / GAME_SIZE_X as f32 * 2., 1. / GAME_SIZE_Y as f32 * 2.),
+        target: vec2(
+            (GAME_SIZE_X as f32 * 0.5f32).floor(),
+            (GAME_SIZE_Y as f32 * 0.5f32).floor(),
+        ),
+        render_target: Some(render_target),
+        ..Default::default()
+    }
+}
+
+
 pub fn variant_eq<T>(a: &T, b: &T) -> bool {
     return std::mem::discriminant(a) == std::mem::discriminant(b); 
 }
@@ -41,14 +73,19 @@ pub fn variant_eq<T>(a: &T, b: &T) -> bool {
 async fn main() {
 
     let game_render_target = render_target(GAME_SIZE_X as u32, GAME_SIZE_Y as u32);
-    let resources = load_resouces(game_render_target).await; 
+    game_render_target.texture.set_filter(FilterMode::Nearest);
+    let resources = load_resouces().await; 
     let mixer = SoundMixer::new();
 
     let game_states: Vec<(GameStateIdentifier, Box<dyn GameState>)> = vec![
         (GameStateIdentifier::Menu, Box::new(GameStateMenu::new())),
         (
             GameStateIdentifier::Game,
-            Box::new(GameStateGame::new(&resources)),
+            Box::new(GameStateGame::new(&resources, PlayerControls::single())),
+        ),
+        (
+            GameStateIdentifier::Versus,
+            Box::new(GameStateVersus::new(&resources)),
         ),
     ];
     
@@ -56,17 +93,7 @@ async fn main() {
 
     loop {
         let dt = get_frame_time();
-        let camera = Camera2D {
-            // I have no idea why the zoom is this way lmao
-            zoom: vec2(1. / GAME_SIZE_X as f32 * 2., 1. / GAME_SIZE_Y as f32 * 2.),
-            target: vec2(
-                (GAME_SIZE_X as f32 * 0.5f32).floor(),
-                (GAME_SIZE_Y as f32 * 0.5f32).floor(),
-            ),
-            render_target: Some(game_render_target),
-            ..Default::default()
-        };
-        set_camera(&camera);
+        set_camera(&game_camera(game_render_target));
         clear_background(BLACK);
 
         game_manager.update(dt);
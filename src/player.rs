@@ -1,10 +1,11 @@
 use macroquad::prelude::*;
-use quad_snd::mixer::{SoundMixer, Volume};
+use quad_snd::mixer::Volume;
 
 use crate:: {
     constants::*, 
-    resources::{Resources, SoundIdentifier},
-    bullet:: { Bullet, BulletHurtType }
+    resources::{Resources, SoundIdentifier, SoundQueue},
+    bullet:: { self, BulletHurtType },
+    ecs::{Collider, Transform, World}
 };
 
 
@@ -20,60 +21,141 @@ pub enum PlayerCommand {
 }
 
 
+// used to get information from the player
+pub enum PlayerMessage {
+    GrazeBurst
+}
+
+
+#[derive(Clone, Copy)]
+pub struct PlayerControls {
+    pub left: KeyCode, 
+    pub right: KeyCode, 
+    pub shoot: KeyCode, 
+    pub special: KeyCode
+}
+
+
+impl PlayerControls {
+    pub fn single() -> Self {
+        PlayerControls {
+            left: KEY_LEFT, 
+            right: KEY_RIGHT, 
+            shoot: KEY_SHOOT, 
+            special: KEY_SPECIAL
+        }
+    }
+
+    pub fn versus_left() -> Self {
+        PlayerControls {
+            left: KEY_VERSUS_P1_LEFT, 
+            right: KEY_VERSUS_P1_RIGHT, 
+            shoot: KEY_VERSUS_P1_SHOOT, 
+            special: KEY_VERSUS_P1_SPECIAL
+        }
+    }
+
+    pub fn versus_right() -> Self {
+        PlayerControls {
+            left: KEY_VERSUS_P2_LEFT, 
+            right: KEY_VERSUS_P2_RIGHT, 
+            shoot: KEY_VERSUS_P2_SHOOT, 
+            special: KEY_VERSUS_P2_SPECIAL
+        }
+    }
+
+    pub fn read(&self) -> PlayerInput {
+        PlayerInput {
+            left: is_key_down(self.left), 
+            right: is_key_down(self.right), 
+            shoot: is_key_down(self.shoot), 
+            special: is_key_pressed(self.special)
+        }
+    }
+}
+
+
+// the buttons held this frame, read from the keyboard or scripted by the replay tests
+#[derive(Clone, Copy, Default)]
+pub struct PlayerInput {
+    pub left: bool, 
+    pub right: bool, 
+    pub shoot: bool, 
+    pub special: bool // only on the frame it goes down
+}
+
+
+// where the player stands is its Transform, the small core hitbox is its Collider
 pub struct Player {
-    pub pos: Vec2, 
+    pub vel: Vec2, // measured from the last frame, enemies use it to lead their shots
     texture: Texture2D, 
     texture_explosion: Texture2D, 
     shoot_timer: f32, 
-    pub collision_rect: Rect, 
+    pub graze_meter: f32, // 0..1, full meter unlocks the graze burst
     pub state: PlayerState, 
-    bullet_decoy_texture: Texture2D
+    bullet_decoy_texture: Texture2D, 
+    shooting: bool // held shoot last update, shows the hitbox
 }
 
 
 impl Player {
     pub fn new(
-        pos: Vec2,
         texture: Texture2D,
         bullet_decoy_texture: Texture2D,
         texture_explosion: Texture2D,
     ) -> Self {
         Player {
-            pos, 
+            vel: vec2(0f32, 0f32), 
             texture,
             bullet_decoy_texture, 
             shoot_timer: 0f32,
             texture_explosion, 
             state: PlayerState::Normal, 
-            collision_rect: Rect::new(pos.x, pos.y, 7.0f32, 7.0f32)
+            graze_meter: 0f32, 
+            shooting: false
         }
     }
 
-    pub fn update(&mut self, dt: f32, bullets: &mut Vec<Bullet>, resources: &Resources, sound_mixer: &mut SoundMixer) {
+    pub fn add_graze(&mut self) {
+        self.graze_meter = (self.graze_meter + PLAYER_GRAZE_METER_PER_BULLET).min(1f32); 
+    }
+
+    pub fn update(
+        &mut self, 
+        transform: &mut Transform, 
+        dt: f32, 
+        input: &PlayerInput, 
+        world: &mut World, 
+        resources: &Resources, 
+        sound_queue: &mut SoundQueue
+    ) -> Option<PlayerMessage> {
         self.shoot_timer += dt; 
+        self.shooting = input.shoot; 
+        let pos = &mut transform.pos; 
+        let last_pos = *pos; 
 
-        if is_key_down(KEY_LEFT) {
-            self.pos.x -= PLAYER_SPEED * dt;
-            if self.pos.x > 0f32 {
-                self.pos.x  = 0f32; 
+        if input.left {
+            pos.x -= PLAYER_SPEED * dt;
+            if pos.x > 0f32 {
+                pos.x  = 0f32; 
             }
         }
 
-        if is_key_down(KEY_RIGHT){
-            self.pos.x += PLAYER_SPEED * dt;
-            if self.pos.x > GAME_SIZE_X as f32 - self.texture.width() {
-                self.pos.x = GAME_SIZE_X as f32 - self.texture.width()
+        if input.right {
+            pos.x += PLAYER_SPEED * dt;
+            if pos.x > GAME_SIZE_X as f32 - self.texture.width() {
+                pos.x = GAME_SIZE_X as f32 - self.texture.width()
             }
         }
 
 
         let player_command_optional = match &mut self.state {
             PlayerState::Normal => {
-                if is_key_down(KEY_SHOOT) && self.shoot_timer >= PLAYER_SHOOT_TIME {
+                if input.shoot && self.shoot_timer >= PLAYER_SHOOT_TIME {
                     let spawn_offset = vec2(3f32, -4f32); 
                     //bullet here
-                    bullets.push(Bullet::new(self.pos + spawn_offset, BulletHurtType::Enermy, resources)); 
-                    resources.play_sound(SoundIdentifier::PlayerShoot, sound_mixer, Volume(1.0f32)); 
+                    bullet::spawn(world, *pos + spawn_offset, BulletHurtType::Enermy, resources); 
+                    resources.play_sound(SoundIdentifier::PlayerShoot, sound_queue, Volume(1.0f32)); 
                     self.shoot_timer = 0f32; 
                 }
 
@@ -94,15 +176,21 @@ impl Player {
         };
 
         self.process_optional_command(player_command_optional); 
-        self.collision_rect.x = self.pos.x;
-        self.collision_rect.y = self.pos.y; 
+        if dt > 0f32 {
+            self.vel = (*pos - last_pos) / dt; 
+        }
 
+        if input.special && self.graze_meter >= 1f32 {
+            self.graze_meter = 0f32; 
+            return Some(PlayerMessage::GrazeBurst); 
+        }
+        None
     }
 
-    pub fn draw(&self) {
+    pub fn draw(&self, transform: &Transform, collider: &Collider) {
         match self.state {
-            PlayerState::Normal => self.draw_state_normal(), 
-            PlayerState::Invincible(time_left) => self.draw_state_invisible(&time_left)
+            PlayerState::Normal => self.draw_state_normal(transform.pos, collider), 
+            PlayerState::Invincible(time_left) => self.draw_state_invisible(transform.pos, &time_left)
         }
     }
 
@@ -116,21 +204,23 @@ impl Player {
         }
     }
 
-    pub fn reset(&mut self, resources: &Resources) {
+    pub fn reset(&mut self, transform: &mut Transform, resources: &Resources) {
         let player_spawn_y =
             GAME_SIZE_Y as f32 - resources.ground_bg.height() - resources.player.height();
         let player_pos = vec2(GAME_CENTER_X, player_spawn_y);
         
-        self.pos = player_pos;
+        transform.pos = player_pos;
         self.shoot_timer = 0f32;
         self.state = PlayerState::Normal; 
+        self.graze_meter = 0f32; 
+        self.shooting = false; 
     }
 
-    pub fn draw_state_normal(&self) {
+    pub fn draw_state_normal(&self, pos: Vec2, collider: &Collider) {
         draw_texture_ex(
             self.texture, 
-            self.pos.x, 
-            self.pos.y, 
+            pos.x, 
+            pos.y, 
             WHITE, 
             DrawTextureParams {
                 ..Default::default()
@@ -142,8 +232,8 @@ impl Player {
 
         draw_texture_ex(
             self.bullet_decoy_texture, 
-            self.pos.x + 3., 
-            self.pos.y - 1., 
+            pos.x + 3., 
+            pos.y - 1., 
             WHITE,
             DrawTextureParams {
                 source: Some(Rect::new(
@@ -155,10 +245,17 @@ impl Player {
                 ..Default::default()
             }
 
-        )
+        );
+
+        // show the real hitbox while firing, makes dodging dense bullets readable
+        // the collider only catches up during the next update, draw the hitbox where the player is now
+        if self.shooting {
+            let hitbox = collider.rect_at(pos); 
+            draw_rectangle(hitbox.x, hitbox.y, hitbox.w, hitbox.h, PINK); 
+        }
     }
 
-    pub fn draw_state_invisible(&self, time_left: &f32){
+    pub fn draw_state_invisible(&self, pos: Vec2, time_left: &f32){
         let anim_frames = 7f32;
         let time_per_frame = PLAYER_TIME_INVISBLE / anim_frames; 
         let fraction = (PLAYER_TIME_INVISBLE - time_left) / PLAYER_TIME_INVISBLE;
@@ -167,8 +264,8 @@ impl Player {
 
         draw_texture_ex(
             self.texture_explosion, 
-            self.pos.x - 0.5f32, 
-            self.pos.y - 0.5f32, 
+            pos.x - 0.5f32, 
+            pos.y - 0.5f32, 
             WHITE, 
             DrawTextureParams {
                 rotation: std::f32::consts::PI  * 2f32, 
@@ -8,7 +8,17 @@ use quad_snd::{
 
 
 use std::collections::HashMap; 
-use crate::enermy::{EnermyColor, EnermyType}; 
+use std::rc::Rc; 
+use crate::{
+    constants::ENERMY_SPRITE_FRAMES, 
+    enermy::{EnermyColor, EnermyType}, 
+    bullet_pattern::BulletPatternLibrary, 
+    enermy_profile::EnermyProfileLibrary, 
+    behaviour::BehaviourLibrary, 
+    spline::PathLibrary, 
+    sprite_mask::SpriteMask, 
+    boss::BOSS_PHASES
+}; 
 
 
 pub struct Resources {
@@ -17,18 +27,48 @@ pub struct Resources {
     pub ground_bg: Texture2D, 
     pub player: Texture2D, 
     pub player_explosion: Texture2D, 
+    pub enermy_explosion: Texture2D, // row of square frames, played when a demon dies
     pub player_missle: Texture2D, 
     pub deamon_missle: Texture2D, 
+    pub flash_material_optional: Option<Material>, // draws a sprite solid white for hit flashes, None without a window
+
+
+    pub demons_normal_purple: Vec<DemonSheet>,
+    pub demons_normal_green: Vec<DemonSheet>,
+    pub demons_normal_red: Vec<DemonSheet>,
+    pub demons_mini_purple: Vec<DemonSheet>,
+    pub demons_mini_green: Vec<DemonSheet>,
+    pub demons_mini_red: Vec<DemonSheet>,
+    pub demons_boss: Vec<DemonSheet>,
+    pub demons_kamikaze: Vec<DemonSheet>,
+    pub demons_shielded: Vec<DemonSheet>,
+    pub demons_sniper: Vec<DemonSheet>,
+
+    pub sounds: HashMap<SoundIdentifier, Sound>, 
+    pub bullet_patterns: BulletPatternLibrary, 
+    pub enermy_profiles: EnermyProfileLibrary, 
+    pub enermy_paths: PathLibrary, 
+    pub enermy_behaviours: BehaviourLibrary
+}
 
 
-    pub demons_normal_purple: Vec<Texture2D>,
-    pub demons_normal_green: Vec<Texture2D>,
-    pub demons_normal_red: Vec<Texture2D>,
-    pub demons_mini_purple: Vec<Texture2D>,
-    pub demons_mini_green: Vec<Texture2D>,
-    pub demons_mini_red: Vec<Texture2D>,
+// one demon sprite sheet and the pixels its frames cover
+#[derive(Clone)]
+pub struct DemonSheet {
+    pub texture: Texture2D, 
+    pub mask_optional: Option<Rc<SpriteMask>> // in sheet pixels, the boss scales it up with the sprite
+}
+
 
-    pub sounds: HashMap<SoundIdentifier, Sound>
+// the single textures, named after the Resources field each one goes into
+pub struct ResourceTextures {
+    pub deamon_missle: Texture2D, 
+    pub player: Texture2D, 
+    pub player_explosion: Texture2D, 
+    pub ground_bg: Texture2D, 
+    pub life: Texture2D, 
+    pub player_missle: Texture2D, 
+    pub enermy_explosion: Texture2D
 }
 
 
@@ -45,32 +85,50 @@ pub enum SoundIdentifier {
 }
 
 
+// sounds asked for during an update, played once the frame is done so the game itself never needs the mixer
+pub struct SoundQueue {
+    queued: Vec<(SoundIdentifier, Volume)>
+}
+
+
+impl SoundQueue {
+    pub fn new() -> Self {
+        SoundQueue {
+            queued: Vec::new()
+        }
+    }
+}
+
+
 //let implement this resouces listed above
 impl Resources {
-    pub fn new(
-        deamon_missle: Texture2D, 
-        player: Texture2D, 
-        player_explosion: Texture2D, 
-        ground_bg: Texture2D, 
-        life: Texture2D,
-        player_missle: Texture2D,
-        font: Font
-    ) -> Self {
+    pub fn new(textures: ResourceTextures, font: Font, flash_material_optional: Option<Material>) -> Self {
+        let ResourceTextures { deamon_missle, player, player_explosion, ground_bg, life, player_missle, enermy_explosion } = textures; 
         Resources {
-            demons_normal_green: Vec::<Texture2D>::new(), 
-            demons_normal_purple: Vec::<Texture2D>::new(), 
-            demons_normal_red: Vec::<Texture2D>::new(),
-            demons_mini_green: Vec::<Texture2D>::new(), 
-            demons_mini_purple: Vec::<Texture2D>::new(), 
-            demons_mini_red: Vec::<Texture2D>::new(),
+            demons_normal_green: Vec::<DemonSheet>::new(), 
+            demons_normal_purple: Vec::<DemonSheet>::new(), 
+            demons_normal_red: Vec::<DemonSheet>::new(),
+            demons_mini_green: Vec::<DemonSheet>::new(), 
+            demons_mini_purple: Vec::<DemonSheet>::new(), 
+            demons_mini_red: Vec::<DemonSheet>::new(),
+            demons_boss: Vec::<DemonSheet>::new(),
+            demons_kamikaze: Vec::<DemonSheet>::new(),
+            demons_shielded: Vec::<DemonSheet>::new(),
+            demons_sniper: Vec::<DemonSheet>::new(),
             deamon_missle, 
             font, 
             player, 
             ground_bg, 
             player_missle, 
             player_explosion,
+            enermy_explosion, 
             life, 
-            sounds: HashMap::new()
+            flash_material_optional, 
+            sounds: HashMap::new(), 
+            bullet_patterns: BulletPatternLibrary::new(), 
+            enermy_profiles: EnermyProfileLibrary::new(), 
+            enermy_paths: PathLibrary::new(), 
+            enermy_behaviours: BehaviourLibrary::new()
         }
     }
 
@@ -80,9 +138,15 @@ impl Resources {
         self.sounds.insert(identifier, sound); 
     }
 
-    pub fn play_sound(&self, identifier: SoundIdentifier, mixer: &mut SoundMixer, volume: Volume) {
-        if let Some(sound) = self.sounds.get(&identifier) {
-            mixer.play_ext(sound.clone(), volume); 
+    pub fn play_sound(&self, identifier: SoundIdentifier, sound_queue: &mut SoundQueue, volume: Volume) {
+        sound_queue.queued.push((identifier, volume)); 
+    }
+
+    pub fn play_queued_sounds(&self, sound_queue: &mut SoundQueue, mixer: &mut SoundMixer) {
+        for (identifier, volume) in sound_queue.queued.drain(..) {
+            if let Some(sound) = self.sounds.get(&identifier) {
+                mixer.play_ext(sound.clone(), volume); 
+            }
         }
     }
 
@@ -93,8 +157,15 @@ impl Resources {
         enemy_color: EnermyColor,
         enemy_type: EnermyType,
     ) -> Result<(), FileError> {
-        let texture: Texture2D = load_texture(file_name).await?;
+        let image = load_image(file_name).await?;
+        let texture = Texture2D::from_image(&image);
         texture.set_filter(FilterMode::Nearest);
+        self.insert_demon_sheet(texture, &image, enemy_color, enemy_type); 
+        Ok(())
+    }
+
+    fn insert_demon_sheet(&mut self, texture: Texture2D, image: &Image, enemy_color: EnermyColor, enemy_type: EnermyType) {
+        let mask_optional = Some(Rc::new(SpriteMask::from_image(image, ENERMY_SPRITE_FRAMES))); 
         let texture_vec = match enemy_type {
             EnermyType::NORMAL => match enemy_color {
                 EnermyColor::PURPLE => &mut self.demons_normal_purple,
@@ -106,30 +177,49 @@ impl Resources {
                 EnermyColor::GREEN => &mut self.demons_mini_green,
                 EnermyColor::RED => &mut self.demons_mini_red,
             },
+            EnermyType::Boss => &mut self.demons_boss,
+            EnermyType::Kamikaze => &mut self.demons_kamikaze,
+            EnermyType::Shielded => &mut self.demons_shielded,
+            EnermyType::Sniper => &mut self.demons_sniper,
         };
-        texture_vec.push(texture);
-        Ok(())
+        texture_vec.push(DemonSheet { texture, mask_optional });
     }
 
 
-    pub fn rand_enemy_normal(&self, enermy_color: EnermyColor ) -> Texture2D{
+    pub fn rand_enemy_normal(&self, enermy_color: EnermyColor ) -> DemonSheet{
         let normal_list = match enermy_color {
             EnermyColor::PURPLE => &self.demons_normal_purple, 
             EnermyColor::GREEN => &self.demons_normal_green, 
             EnermyColor::RED => &self.demons_normal_red
         }; 
 
-        normal_list[rand::gen_range(0, normal_list.len())]
+        normal_list[rand::gen_range(0, normal_list.len())].clone()
     }
 
-    pub fn rand_enermy_mini(&self, enermy_color: EnermyColor) -> Texture2D {
+    pub fn rand_enermy_mini(&self, enermy_color: EnermyColor) -> DemonSheet {
         let mini_list = match enermy_color {
             EnermyColor::PURPLE => &self.demons_mini_purple, 
             EnermyColor::GREEN => &self.demons_mini_green, 
             EnermyColor::RED => &self.demons_mini_red
         }; 
 
-        mini_list[rand::gen_range(0, mini_list.len())]
+        mini_list[rand::gen_range(0, mini_list.len())].clone()
+    }
+
+    // the archetypes look the same whatever their color
+    pub fn rand_enermy_archetype(&self, enermy_type: EnermyType) -> DemonSheet {
+        let archetype_list = match enermy_type {
+            EnermyType::Kamikaze => &self.demons_kamikaze, 
+            EnermyType::Shielded => &self.demons_shielded, 
+            EnermyType::Sniper => &self.demons_sniper, 
+            EnermyType::NORMAL | EnermyType::MINI | EnermyType::Boss => &self.demons_normal_purple
+        }; 
+
+        archetype_list[rand::gen_range(0, archetype_list.len())].clone()
+    }
+
+    pub fn rand_enermy_boss(&self) -> DemonSheet {
+        self.demons_boss[rand::gen_range(0, self.demons_boss.len())].clone()
     }
 
 
@@ -143,9 +233,71 @@ const SOUND_BYTES_ENEMY_SHOOT: &[u8] = include_bytes!("../assets/sounds/enermy_s
 const SOUND_BYTES_PLAYER_SHOOT: &[u8] = include_bytes!("../assets/sounds/player_shoot.wav");
 const SOUND_BYTES_WAVE_CLEARED: &[u8] = include_bytes!("../assets/sounds/wave_cleared.wav");
 const SOUND_BYTES_PLAYER_OUCH: &[u8] = include_bytes!("../assets/sounds/player_ouch.wav");
+const SOUND_BYTES_WARNING: &[u8] = include_bytes!("../assets/sounds/warning.wav");
+const PATTERNS_DEMONS: &str = include_str!("../assets/patterns/demons.pattern");
+const PROFILES_DEMONS: &str = include_str!("../assets/profiles/demons.profile");
+const WAVES_DEMONS: &str = include_str!("../assets/waves/demons.wave");
+const BEHAVIOURS_DEMONS: &str = include_str!("../assets/behaviours/demons.behaviour");
+
+
+const SHADER_VERTEX_DEFAULT: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+varying lowp vec2 uv;
+varying lowp vec4 color;
+uniform mat4 Model;
+uniform mat4 Projection;
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+const SHADER_FRAGMENT_FLASH: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+void main() {
+    gl_FragColor = vec4(1.0, 1.0, 1.0, texture2D(Texture, uv).a * color.a);
+}"#;
+
+
+fn load_flash_material() -> Material {
+    use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation}; 
+    let pipeline_params = PipelineParams {
+        color_blend: Some(BlendState::new(
+            Equation::Add, 
+            BlendFactor::Value(BlendValue::SourceAlpha), 
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha)
+        )), 
+        ..Default::default()
+    }; 
+    load_material(
+        SHADER_VERTEX_DEFAULT, 
+        SHADER_FRAGMENT_FLASH, 
+        MaterialParams { pipeline_params, ..Default::default() }
+    ).unwrap()
+}
 
 
-pub async fn load_resouces(game_render_target: RenderTarget ) -> Resources {
+// every demon sheet with the color and type it is picked for
+const DEMON_SHEETS: [(&str, EnermyColor, EnermyType); 12] = [
+    ("assets/demon_mini_green_1.png", EnermyColor::GREEN, EnermyType::MINI), 
+    ("assets/demon_mini_red_1.png", EnermyColor::RED, EnermyType::MINI), 
+    ("assets/demon_mini_purple_1.png", EnermyColor::PURPLE, EnermyType::MINI), 
+    ("assets/demon_normal_green_1.png", EnermyColor::GREEN, EnermyType::NORMAL), 
+    ("assets/demon_normal_green_2.png", EnermyColor::GREEN, EnermyType::NORMAL), 
+    ("assets/demon_normal_purple_1.png", EnermyColor::PURPLE, EnermyType::NORMAL), 
+    ("assets/demon_normal_purple_2.png", EnermyColor::PURPLE, EnermyType::NORMAL), 
+    ("assets/demon_normal_red_1.png", EnermyColor::RED, EnermyType::NORMAL), 
+    ("assets/demon_normal_red_1.png", EnermyColor::RED, EnermyType::Boss), 
+    ("assets/demon_kamikaze_1.png", EnermyColor::RED, EnermyType::Kamikaze), 
+    ("assets/demon_shielded_1.png", EnermyColor::PURPLE, EnermyType::Shielded), 
+    ("assets/demon_sniper_1.png", EnermyColor::GREEN, EnermyType::Sniper)
+];
+
+
+pub async fn load_resouces() -> Resources {
     
     let texture_player: Texture2D = load_texture("assets/player.png").await.unwrap();
     let texture_player_explosion: Texture2D = load_texture("assets/player_explotion.png").await.unwrap(); 
@@ -162,39 +314,96 @@ pub async fn load_resouces(game_render_target: RenderTarget ) -> Resources {
         texture_demon_missile,
         texture_ground_bg,
         texture_life,
-        game_render_target.texture,
     ].iter() {
         texture.set_filter(FilterMode::Nearest)
     }
 
     let font: Font = load_ttf_font("assets/Kenney Pixel Square.ttf").await.unwrap(); 
-    let mut resources = Resources::new(
-        texture_demon_missile, 
-        texture_player_missile, 
-        texture_player, 
-        texture_ground_bg, 
-        texture_life, 
-        texture_player_missile, 
-        font
-    ); 
-
-    {
-        use EnermyColor::{GREEN, PURPLE, RED}; 
-        use EnermyType:: { MINI, NORMAL }; 
-        resources.load_texture("assets/demon_mini_green_1.png", GREEN, MINI).await.unwrap(); 
-        resources.load_texture("assets/demon_mini_red_1.png", RED, MINI).await.unwrap();
-        resources.load_texture("assets/demon_mini_purple_1.png", PURPLE, MINI).await.unwrap();
-        resources.load_texture("assets/demon_normal_green_1.png", GREEN, NORMAL).await.unwrap();
-        resources.load_texture("assets/demon_normal_green_2.png", GREEN, NORMAL).await.unwrap();
-        resources.load_texture("assets/demon_normal_purple_1.png", PURPLE, NORMAL).await.unwrap();
-        resources.load_texture("assets/demon_normal_purple_2.png", PURPLE, NORMAL).await.unwrap();
-        resources.load_texture("assets/demon_normal_red_1.png", RED, NORMAL).await.unwrap();
-    } 
+    let textures = ResourceTextures {
+        deamon_missle: texture_demon_missile, 
+        player: texture_player_missile, 
+        player_explosion: texture_player, 
+        ground_bg: texture_ground_bg, 
+        life: texture_life, 
+        player_missle: texture_player_missile, 
+        enermy_explosion: texture_player_explosion
+    }; 
+    let mut resources = Resources::new(textures, font, Some(load_flash_material())); 
+
+    for (file_name, enermy_color, enermy_type) in DEMON_SHEETS.iter() {
+        resources.load_texture(file_name, *enermy_color, *enermy_type).await.unwrap(); 
+    }
     {
         use SoundIdentifier::*; 
         resources.load_sound(SOUND_BYTES_ENEMY_SHOOT, EnermyShoot); 
         resources.load_sound(SOUND_BYTES_PLAYER_SHOOT, PlayerShoot); 
+        resources.load_sound(SOUND_BYTES_WARNING, Warning); 
     }
 
+    load_data(&mut resources); 
     resources
-}
\ No newline at end of file
+}
+
+
+// patterns, profiles, paths and behaviours, checked against each other so typos show up on startup
+fn load_data(resources: &mut Resources) {
+    resources.bullet_patterns.load(PATTERNS_DEMONS).unwrap(); 
+    resources.enermy_profiles.load(PROFILES_DEMONS).unwrap(); 
+    resources.enermy_paths.load(WAVES_DEMONS).unwrap(); 
+    resources.enermy_behaviours.load(BEHAVIOURS_DEMONS).unwrap(); 
+    for enermy_color in [EnermyColor::PURPLE, EnermyColor::RED, EnermyColor::GREEN] {
+        assert!(resources.enermy_profiles.contains(enermy_color.name()), "missing enemy profile `{}`", enermy_color.name()); 
+    }
+    for enermy_type in [EnermyType::Kamikaze, EnermyType::Shielded, EnermyType::Sniper] {
+        let profile_name = enermy_type.profile_name(EnermyColor::PURPLE); 
+        assert!(resources.enermy_profiles.contains(profile_name), "missing enemy profile `{}`", profile_name); 
+    }
+
+    // catch typos in pattern names on startup instead of when the boss shows up
+    let pattern_names = BOSS_PHASES.iter().map(|phase| phase.pattern)
+        .chain(resources.enermy_profiles.pattern_names())
+        .chain(resources.enermy_behaviours.pattern_names()); 
+    for pattern_name in pattern_names {
+        assert!(resources.bullet_patterns.contains(pattern_name), "missing bullet pattern `{}`", pattern_name); 
+    }
+    for path_name in resources.enermy_profiles.path_names() {
+        assert!(resources.enermy_paths.contains(path_name), "missing path `{}`", path_name); 
+    }
+    for behaviour_name in resources.enermy_profiles.behaviour_names() {
+        assert!(resources.enermy_behaviours.contains(behaviour_name), "missing behaviour tree `{}`", behaviour_name); 
+    }
+}
+
+
+// the same resources without a window for the replay tests, textures are never uploaded and only keep their size
+#[cfg(test)]
+pub fn load_resources_headless() -> Resources {
+    fn load_image(file_name: &str) -> Image {
+        Image::from_file_with_format(&std::fs::read(file_name).unwrap(), Some(ImageFormat::Png))
+    }
+    fn blank_texture(image: &Image) -> Texture2D {
+        let mut texture = miniquad::Texture::empty(); 
+        texture.width = image.width as u32; 
+        texture.height = image.height as u32; 
+        Texture2D::from_miniquad_texture(texture)
+    }
+    let load_texture = |file_name: &str| blank_texture(&load_image(file_name)); 
+
+    let texture_player_missile = load_texture("assets/player_missile.png"); 
+    let textures = ResourceTextures {
+        deamon_missle: load_texture("assets/demon_missile.png"), 
+        player: texture_player_missile, 
+        player_explosion: load_texture("assets/player.png"), 
+        ground_bg: load_texture("assets/ground_bg.png"), 
+        life: load_texture("assets/life.png"), 
+        player_missle: texture_player_missile, 
+        enermy_explosion: load_texture("assets/player_explotion.png")
+    }; 
+    let mut resources = Resources::new(textures, Font::default(), None); 
+    for (file_name, enermy_color, enermy_type) in DEMON_SHEETS.iter() {
+        let image = load_image(file_name); 
+        resources.insert_demon_sheet(blank_texture(&image), &image, *enermy_color, *enermy_type); 
+    }
+    load_data(&mut resources); 
+    resources
+}
@@ -0,0 +1,127 @@
+// tiny s-expression reader used by the data files in assets/
+// `;` starts a comment that runs to the end of the line
+
+
+#[derive(Clone, Debug)]
+pub enum Sexpr {
+    Atom(String),
+    List(Vec<Sexpr>)
+}
+
+
+impl Sexpr {
+    pub fn as_atom(&self) -> Result<&str, String> {
+        match self {
+            Sexpr::Atom(atom) => Ok(atom.as_str()),
+            Sexpr::List(_) => Err(format!("expected an atom, found {}", self))
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&[Sexpr], String> {
+        match self {
+            Sexpr::List(list) => Ok(list.as_slice()),
+            Sexpr::Atom(atom) => Err(format!("expected a list, found `{}`", atom))
+        }
+    }
+
+    pub fn as_f32(&self) -> Result<f32, String> {
+        let atom = self.as_atom()?;
+        atom.parse::<f32>().map_err(|_| format!("expected a number, found `{}`", atom))
+    }
+
+    // splits `(name args...)` into the name and its arguments
+    pub fn as_call(&self) -> Result<(&str, &[Sexpr]), String> {
+        let list = self.as_list()?;
+        match list.split_first() {
+            Some((head, args)) => Ok((head.as_atom()?, args)),
+            None => Err(String::from("expected a list starting with a name, found ()"))
+        }
+    }
+}
+
+
+impl std::fmt::Display for Sexpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Sexpr::Atom(atom) => write!(f, "`{}`", atom),
+            Sexpr::List(list) => write!(f, "a list of {} items", list.len())
+        }
+    }
+}
+
+
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::<String>::new();
+    for line in source.lines() {
+        let line = match line.find(';') {
+            Some(comment_start) => &line[..comment_start],
+            None => line
+        };
+
+        let spaced = line.replace('(', " ( ").replace(')', " ) ");
+        tokens.extend(spaced.split_whitespace().map(String::from));
+    }
+    tokens
+}
+
+
+// parses every top level expression in the source
+pub fn parse(source: &str) -> Result<Vec<Sexpr>, String> {
+    let tokens = tokenize(source);
+    let mut stack: Vec<Vec<Sexpr>> = vec![Vec::new()];
+
+    for token in tokens {
+        match token.as_str() {
+            "(" => stack.push(Vec::new()),
+            ")" => {
+                if stack.len() < 2 {
+                    return Err(String::from("unexpected `)`"));
+                }
+                let list = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Sexpr::List(list));
+            }
+            _ => stack.last_mut().unwrap().push(Sexpr::Atom(token))
+        }
+    }
+
+    if stack.len() > 1 {
+        return Err(String::from("missing `)` at end of file"));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_nested_lists_and_skips_comments() {
+        let exprs = parse("(pattern ring ; eight at once\n  (fire (ring 8)))\nloose").unwrap();
+        assert_eq!(exprs.len(), 2);
+        let (head, args) = exprs[0].as_call().unwrap();
+        assert_eq!(head, "pattern");
+        assert_eq!(args[0].as_atom().unwrap(), "ring");
+        let (head, specs) = args[1].as_call().unwrap();
+        assert_eq!(head, "fire");
+        assert_eq!(specs[0].as_list().unwrap()[1].as_f32().unwrap(), 8f32);
+        assert_eq!(exprs[1].as_atom().unwrap(), "loose");
+    }
+
+    #[test]
+    fn rejects_unbalanced_parens() {
+        assert_eq!(parse("(fire (ring 8)").unwrap_err(), "missing `)` at end of file");
+        assert_eq!(parse("(fire))").unwrap_err(), "unexpected `)`");
+        // a paren inside a comment doesn't count
+        assert!(parse("(fire) ; )").is_ok());
+    }
+
+    #[test]
+    fn rejects_the_wrong_shape() {
+        let exprs = parse("() fast (1 2)").unwrap();
+        assert!(exprs[0].as_call().is_err());
+        assert_eq!(exprs[1].as_f32().unwrap_err(), "expected a number, found `fast`");
+        assert_eq!(exprs[1].as_list().unwrap_err(), "expected a list, found `fast`");
+        assert_eq!(exprs[2].as_atom().unwrap_err(), "expected an atom, found a list of 2 items");
+    }
+}
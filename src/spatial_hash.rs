@@ -0,0 +1,135 @@
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+
+// uniform grid rebuilt every frame, so collision checks only look at things in the same few cells
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<T>> // indices or handles into whatever got inserted
+}
+
+
+impl<T: Copy + Ord> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new()
+        }
+    }
+
+    // keeps the cells around so the next frame doesn't allocate them again
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    fn cell_range(&self, rect: &Rect) -> (i32, i32, i32, i32) {
+        (
+            (rect.x / self.cell_size).floor() as i32,
+            (rect.y / self.cell_size).floor() as i32,
+            ((rect.x + rect.w) / self.cell_size).floor() as i32,
+            ((rect.y + rect.h) / self.cell_size).floor() as i32
+        )
+    }
+
+    pub fn insert(&mut self, item: T, rect: &Rect) {
+        let (left, top, right, bottom) = self.cell_range(rect);
+        for y in top..=bottom {
+            for x in left..=right {
+                self.cells.entry((x, y)).or_default().push(item);
+            }
+        }
+    }
+
+    // everything sharing a cell with rect, sorted and without duplicates, still needs an exact overlap check
+    pub fn query(&self, rect: &Rect, found: &mut Vec<T>) {
+        found.clear();
+        let (left, top, right, bottom) = self.cell_range(rect);
+        for y in top..=bottom {
+            for x in left..=right {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    found.extend_from_slice(cell);
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+
+    // bullet sized rects scattered over the playfield, same seed every run
+    fn scatter(count: usize) -> Vec<Rect> {
+        rand::srand(count as u64);
+        (0..count)
+            .map(|_| Rect::new(
+                rand::gen_range(0f32, GAME_SIZE_X as f32),
+                rand::gen_range(0f32, GAME_SIZE_Y as f32),
+                rand::gen_range(2f32, 10f32),
+                rand::gen_range(2f32, 10f32)
+            ))
+            .collect()
+    }
+
+    fn brute_force_pairs(rects: &[Rect]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (n, rect) in rects.iter().enumerate() {
+            for (other, other_rect) in rects.iter().enumerate().skip(n + 1) {
+                if rect.overlaps(other_rect) {
+                    pairs.push((n, other));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn hashed_pairs(rects: &[Rect]) -> Vec<(usize, usize)> {
+        let mut hash = SpatialHash::new(SPATIAL_HASH_CELL_SIZE);
+        for (n, rect) in rects.iter().enumerate() {
+            hash.insert(n, rect);
+        }
+        let mut pairs = Vec::new();
+        let mut found = Vec::new();
+        for (n, rect) in rects.iter().enumerate() {
+            hash.query(rect, &mut found);
+            for other in found.iter().filter(|other| **other > n) {
+                if rect.overlaps(&rects[*other]) {
+                    pairs.push((n, *other));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn finds_the_same_pairs_as_brute_force() {
+        for count in [100, 1000, 5000].iter() {
+            let rects = scatter(*count);
+            let pairs = brute_force_pairs(&rects);
+            assert!(!pairs.is_empty());
+            assert_eq!(hashed_pairs(&rects), pairs);
+        }
+    }
+
+    #[test]
+    fn finds_rects_spanning_several_cells() {
+        let mut hash = SpatialHash::new(SPATIAL_HASH_CELL_SIZE);
+        hash.insert(0, &Rect::new(0f32, 0f32, SPATIAL_HASH_CELL_SIZE * 3f32, 4f32));
+        hash.insert(1, &Rect::new(-20f32, -20f32, 4f32, 4f32));
+        let mut found = Vec::new();
+        hash.query(&Rect::new(SPATIAL_HASH_CELL_SIZE * 2.5f32, 0f32, 1f32, 1f32), &mut found);
+        assert_eq!(found, vec![0]);
+        hash.query(&Rect::new(-19f32, -19f32, 40f32, 20f32), &mut found);
+        assert_eq!(found, vec![0, 1]);
+
+        hash.clear();
+        hash.query(&Rect::new(-19f32, -19f32, 40f32, 20f32), &mut found);
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use macroquad::prelude::*;
+use std::{collections::HashMap, rc::Rc};
+
+use crate::sexpr::{self, Sexpr};
+
+
+const SPLINE_SAMPLES_PER_SEGMENT: usize = 16;
+
+
+// a path baked into a dense polyline, so followers can move along it at an even speed
+pub struct SplinePath {
+    samples: Vec<Vec2>,
+    distances: Vec<f32>, // distance along the path at every sample
+    speed: f32,
+    speed_curve: Vec<f32>, // speed multipliers spread evenly along the path
+    looping: bool
+}
+
+
+impl SplinePath {
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    pub fn point_at(&self, distance: f32) -> Vec2 {
+        // a NaN distance would sort past the end, so it counts as the start
+        let distance = if distance.is_finite() { distance.clamp(0f32, self.length()) } else { 0f32 };
+        let index = match self.distances.binary_search_by(|d| d.total_cmp(&distance)) {
+            Ok(index) => return self.samples[index],
+            Err(index) => index
+        };
+
+        let (start, end) = (self.distances[index - 1], self.distances[index]);
+        let fraction = (distance - start) / (end - start);
+        self.samples[index - 1] + (self.samples[index] - self.samples[index - 1]) * fraction
+    }
+
+    pub fn speed_at(&self, distance: f32) -> f32 {
+        if self.speed_curve.len() < 2 {
+            return self.speed * self.speed_curve.first().copied().unwrap_or(1f32);
+        }
+
+        let position = (distance / self.length()).clamp(0f32, 1f32) * (self.speed_curve.len() - 1) as f32;
+        let index = (position.floor() as usize).min(self.speed_curve.len() - 2);
+        let fraction = position - index as f32;
+        let multiplier = self.speed_curve[index] + (self.speed_curve[index + 1] - self.speed_curve[index]) * fraction;
+        self.speed * multiplier
+    }
+}
+
+
+// moves one enemy along a path, the path points are relative to origin
+pub struct PathFollower {
+    path: Rc<SplinePath>,
+    distance: f32,
+    pub origin: Vec2
+}
+
+
+impl PathFollower {
+    pub fn is_finished(&self) -> bool {
+        !self.path.looping && self.distance >= self.path.length()
+    }
+
+    pub fn update(&mut self, dt: f32) -> Vec2 {
+        // never stall, a path with speed 0 somewhere in its curve would stop the enemy for good
+        self.distance += self.path.speed_at(self.distance).max(1f32) * dt;
+        if self.path.looping {
+            self.distance %= self.path.length().max(1f32);
+        }
+        self.origin + self.path.point_at(self.distance)
+    }
+
+    // moves the whole path so it carries on from pos, after the enemy was somewhere else for a while
+    pub fn continue_from(&mut self, pos: Vec2) {
+        self.origin = pos - self.path.point_at(self.distance);
+    }
+
+    pub fn draw_debug(&self, color: Color) {
+        for pair in self.path.samples.windows(2) {
+            let (a, b) = (self.origin + pair[0], self.origin + pair[1]);
+            draw_line(a.x, a.y, b.x, b.y, 0.5f32, color);
+        }
+        let current = self.origin + self.path.point_at(self.distance);
+        draw_circle_lines(current.x, current.y, 2f32, 0.5f32, color);
+    }
+}
+
+
+pub struct PathLibrary {
+    paths: HashMap<String, Rc<SplinePath>>
+}
+
+
+impl PathLibrary {
+    pub fn new() -> Self {
+        PathLibrary {
+            paths: HashMap::new()
+        }
+    }
+
+    // takes the `path` entries of a wave file and skips everything else
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        for expr in sexpr::parse(source)?.iter() {
+            let (head, args) = expr.as_call()?;
+            if head != "path" {
+                continue;
+            }
+
+            let (name, settings) = args.split_first().ok_or("path without a name")?;
+            let name = name.as_atom()?;
+            let path = compile_path(settings).map_err(|e| format!("path `{}`: {}", name, e))?;
+            self.paths.insert(String::from(name), Rc::new(path));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.paths.contains_key(name)
+    }
+
+    pub fn follower(&self, name: &str, origin: Vec2) -> Option<PathFollower> {
+        self.paths.get(name).map(|path| PathFollower {
+            path: path.clone(),
+            distance: 0f32,
+            origin
+        })
+    }
+}
+
+
+fn compile_points(exprs: &[Sexpr]) -> Result<Vec<Vec2>, String> {
+    exprs.iter()
+        .map(|expr| match expr.as_list()? {
+            [x, y] => Ok(vec2(x.as_f32()?, y.as_f32()?)),
+            _ => Err(format!("expected a point (x y), found {}", expr))
+        })
+        .collect()
+}
+
+
+fn compile_path(settings: &[Sexpr]) -> Result<SplinePath, String> {
+    let mut samples_optional: Option<Vec<Vec2>> = None;
+    let mut path = SplinePath {
+        samples: Vec::new(),
+        distances: Vec::new(),
+        speed: 20f32,
+        speed_curve: Vec::new(),
+        looping: false
+    };
+
+    let is_looping = settings.iter().any(|setting| matches!(setting.as_call(), Ok(("loop", []))));
+    for setting in settings.iter() {
+        match setting.as_call()? {
+            ("catmull-rom", points) => {
+                let points = compile_points(points)?;
+                if points.len() < 2 {
+                    return Err(String::from("catmull-rom needs at least 2 points"));
+                }
+                samples_optional = Some(sample_catmull_rom(&points, is_looping));
+            }
+            ("bezier", points) => {
+                let points = compile_points(points)?;
+                if points.len() < 4 || (points.len() - 1) % 3 != 0 {
+                    return Err(String::from("bezier needs 4 points, then 3 more for every extra segment"));
+                }
+                if is_looping && points.first() != points.last() {
+                    return Err(String::from("a looping bezier has to end where it starts"));
+                }
+                samples_optional = Some(sample_bezier(&points));
+            }
+            ("speed", [value]) => path.speed = value.as_f32()?,
+            ("speed-curve", values) => {
+                path.speed_curve = values.iter().map(|value| value.as_f32()).collect::<Result<Vec<f32>, String>>()?;
+            }
+            ("loop", []) => path.looping = true,
+            (head, args) => return Err(format!("unknown setting `{}` with {} arguments", head, args.len()))
+        }
+    }
+
+    path.samples = samples_optional.ok_or("path without points, add (catmull-rom ...) or (bezier ...)")?;
+    let mut distance = 0f32;
+    path.distances.push(0f32);
+    for pair in path.samples.windows(2) {
+        distance += pair[0].distance(pair[1]);
+        path.distances.push(distance);
+    }
+    if distance <= 0f32 {
+        return Err(String::from("path has no length"));
+    }
+    Ok(path)
+}
+
+
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2f32
+        + (p2 - p0) * t
+        + (p0 * 2f32 - p1 * 5f32 + p2 * 4f32 - p3) * t2
+        + (p1 * 3f32 - p0 - p2 * 3f32 + p3) * t3) * 0.5f32
+}
+
+
+// goes through every point, a looping path also closes the gap from the last point to the first
+fn sample_catmull_rom(points: &[Vec2], looping: bool) -> Vec<Vec2> {
+    let count = points.len() as i32;
+    let point = |index: i32| {
+        if looping {
+            points[index.rem_euclid(count) as usize]
+        } else {
+            points[index.max(0).min(count - 1) as usize]
+        }
+    };
+
+    let segments = if looping { count } else { count - 1 };
+    let mut samples = Vec::<Vec2>::new();
+    for segment in 0..segments {
+        for step in 0..SPLINE_SAMPLES_PER_SEGMENT {
+            let t = step as f32 / SPLINE_SAMPLES_PER_SEGMENT as f32;
+            samples.push(catmull_rom(point(segment - 1), point(segment), point(segment + 1), point(segment + 2), t));
+        }
+    }
+    samples.push(point(segments));
+    samples
+}
+
+
+fn sample_bezier(points: &[Vec2]) -> Vec<Vec2> {
+    let mut samples = Vec::<Vec2>::new();
+    for segment in points.windows(4).step_by(3) {
+        for step in 0..SPLINE_SAMPLES_PER_SEGMENT {
+            let t = step as f32 / SPLINE_SAMPLES_PER_SEGMENT as f32;
+            samples.push(bezier([segment[0], segment[1], segment[2], segment[3]], t));
+        }
+    }
+    samples.push(*points.last().unwrap());
+    samples
+}
+
+
+// cubic bezier, also used for the formation entering and diving paths
+pub fn bezier(points: [Vec2; 4], t: f32) -> Vec2 {
+    let u = 1f32 - t;
+    points[0] * (u * u * u)
+        + points[1] * (3f32 * u * u * t)
+        + points[2] * (3f32 * u * t * t)
+        + points[3] * (t * t * t)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(source: &str) -> Result<PathLibrary, String> {
+        let mut library = PathLibrary::new();
+        library.load(source)?;
+        Ok(library)
+    }
+
+    #[test]
+    fn keeps_nan_and_out_of_range_distances_on_the_path() {
+        let library = load("(path line (catmull-rom (0 0) (100 0)) (speed 50))").unwrap();
+        let path = library.paths.get("line").unwrap();
+        assert!((path.length() - 100f32).abs() < 0.001f32);
+        assert_eq!(path.point_at(f32::NAN), vec2(0f32, 0f32));
+        assert_eq!(path.point_at(f32::INFINITY), vec2(0f32, 0f32));
+        assert_eq!(path.point_at(-10f32), vec2(0f32, 0f32));
+        assert_eq!(path.point_at(1000f32), vec2(100f32, 0f32));
+        assert!((path.point_at(25f32) - vec2(25f32, 0f32)).length() < 0.001f32);
+    }
+
+    #[test]
+    fn followers_stop_at_the_end_or_loop_around() {
+        let library = load("\
+            (path line (catmull-rom (0 0) (100 0)) (speed 50))
+            (path circuit (bezier (0 0) (50 -50) (50 50) (0 0)) (speed 50) (loop))
+            (wave ignored)").unwrap();
+
+        let mut line = library.follower("line", vec2(10f32, 20f32)).unwrap();
+        assert!((line.update(1f32) - vec2(60f32, 20f32)).length() < 0.001f32);
+        assert!(!line.is_finished());
+        assert!((line.update(2f32) - vec2(110f32, 20f32)).length() < 0.001f32);
+        assert!(line.is_finished());
+
+        let mut circuit = library.follower("circuit", vec2(0f32, 0f32)).unwrap();
+        for _ in 0..600 {
+            let pos = circuit.update(1f32 / 60f32);
+            assert!(pos.x.is_finite() && pos.y.is_finite());
+        }
+        assert!(!circuit.is_finished());
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        assert_eq!(load("(path)").err().unwrap(), "path without a name");
+        assert_eq!(load("(path still (speed 10))").err().unwrap(), "path `still`: path without points, add (catmull-rom ...) or (bezier ...)");
+        assert_eq!(load("(path dot (catmull-rom (5 5)))").err().unwrap(), "path `dot`: catmull-rom needs at least 2 points");
+        assert_eq!(load("(path dot (catmull-rom (5 5) (5 5)))").err().unwrap(), "path `dot`: path has no length");
+        assert_eq!(load("(path arc (bezier (0 0) (1 1) (2 2)))").err().unwrap(), "path `arc`: bezier needs 4 points, then 3 more for every extra segment");
+        assert_eq!(load("(path arc (bezier (0 0) (1 1) (2 2) (3 3)) (loop))").err().unwrap(), "path `arc`: a looping bezier has to end where it starts");
+        assert_eq!(load("(path arc (catmull-rom (0 0) (1 1 1)))").err().unwrap(), "path `arc`: expected a point (x y), found a list of 3 items");
+        assert_eq!(load("(path arc (catmull-rom (0 0) (1 1)) (loop 2))").err().unwrap(), "path `arc`: unknown setting `loop` with 1 arguments");
+    }
+}
@@ -0,0 +1,42 @@
+use macroquad::prelude::*;
+
+
+// which pixels of every animation frame are actually drawn, built once from the sprite alpha
+pub struct SpriteMask {
+    pub frame_width: i32,
+    pub frame_height: i32,
+    frames: Vec<Vec<bool>> // one entry per pixel, row by row
+}
+
+
+impl SpriteMask {
+    // frames sit side by side in the sheet, like every sprite in assets
+    pub fn from_image(image: &Image, frame_count: i32) -> Self {
+        let frame_width = image.width as i32 / frame_count;
+        let frame_height = image.height as i32;
+        let frames = (0..frame_count)
+            .map(|frame| {
+                let mut solid = Vec::with_capacity((frame_width * frame_height) as usize);
+                for y in 0..frame_height {
+                    for x in 0..frame_width {
+                        let pixel = image.get_pixel((frame * frame_width + x) as u32, y as u32);
+                        solid.push(pixel.a > 0f32);
+                    }
+                }
+                solid
+            })
+            .collect();
+
+        SpriteMask {
+            frame_width,
+            frame_height,
+            frames
+        }
+    }
+
+    pub fn is_solid(&self, frame: usize, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.frame_width && y < self.frame_height
+            && self.frames[frame % self.frames.len()][(y * self.frame_width + x) as usize]
+    }
+}
+
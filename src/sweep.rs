@@ -0,0 +1,115 @@
+use macroquad::prelude::*;
+
+
+// rect moving by delta against a still target, the fraction of delta where they first touch
+pub fn time_of_impact(rect: &Rect, delta: Vec2, target: &Rect) -> Option<f32> {
+    if rect.overlaps(target) {
+        return Some(0f32);
+    }
+
+    // grow the target by the moving rect so only its corner has to be traced
+    let (mut enter, mut exit) = (0f32, 1f32);
+    let slabs = [
+        (rect.x, delta.x, target.x - rect.w, target.x + target.w),
+        (rect.y, delta.y, target.y - rect.h, target.y + target.h)
+    ];
+    for (start, step, min, max) in slabs.iter() {
+        if *step == 0f32 {
+            if *start < *min || *start > *max {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((min - start) / step, (max - start) / step);
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+        if enter > exit {
+            return None;
+        }
+    }
+    Some(enter)
+}
+
+
+// copies of rect along delta from the fraction start on, never more than a pixel apart, ends on the full move
+pub fn path(rect: Rect, delta: Vec2, start: f32) -> impl Iterator<Item = Rect> {
+    let steps = (delta.length() * (1f32 - start)).ceil().max(1f32) as i32;
+    (0..=steps).map(move |i| {
+        let t = start + (1f32 - start) * i as f32 / steps as f32;
+        Rect::new(rect.x + delta.x * t, rect.y + delta.y * t, rect.w, rect.h)
+    })
+}
+
+
+// everything rect touches on its way, for the broadphase
+pub fn bounds(rect: &Rect, delta: Vec2) -> Rect {
+    Rect::new(
+        rect.x + delta.x.min(0f32),
+        rect.y + delta.y.min(0f32),
+        rect.w + delta.x.abs(),
+        rect.h + delta.y.abs()
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_delta_hits_a_thin_target() {
+        // a dt spike of half a second at 400px/s, straight through a one pixel line
+        let rect = Rect::new(0f32, 0f32, 4f32, 4f32);
+        let target = Rect::new(0f32, 100f32, 4f32, 1f32);
+        let toi = time_of_impact(&rect, vec2(0f32, 200f32), &target).unwrap();
+        assert!((toi - 0.48f32).abs() < 1e-6);
+        assert!(path(rect, vec2(0f32, 200f32), 0f32).any(|step| step.overlaps(&target)));
+        assert!(!Rect::new(0f32, 200f32, 4f32, 4f32).overlaps(&target));
+    }
+
+    #[test]
+    fn parallel_move_misses() {
+        let rect = Rect::new(0f32, 0f32, 4f32, 4f32);
+        let target = Rect::new(20f32, 10f32, 4f32, 4f32);
+        assert_eq!(time_of_impact(&rect, vec2(100f32, 0f32), &target), None);
+        assert_eq!(time_of_impact(&rect, vec2(0f32, 0f32), &target), None);
+    }
+
+    #[test]
+    fn moving_away_misses() {
+        let rect = Rect::new(0f32, 0f32, 4f32, 4f32);
+        let target = Rect::new(0f32, 10f32, 4f32, 4f32);
+        assert_eq!(time_of_impact(&rect, vec2(0f32, -50f32), &target), None);
+    }
+
+    #[test]
+    fn already_overlapping_is_zero() {
+        let rect = Rect::new(0f32, 0f32, 4f32, 4f32);
+        let target = Rect::new(2f32, 2f32, 4f32, 4f32);
+        assert_eq!(time_of_impact(&rect, vec2(30f32, -30f32), &target), Some(0f32));
+    }
+
+    #[test]
+    fn path_steps_a_pixel_at_most_and_ends_on_the_move() {
+        let rect = Rect::new(3f32, 5f32, 4f32, 2f32);
+        let delta = vec2(-37.5f32, 120.25f32);
+        for start in [0f32, 0.3f32, 1f32].iter() {
+            let steps: Vec<Rect> = path(rect, delta, *start).collect();
+            assert!(steps.len() >= 2);
+            assert_eq!(steps[0].point(), rect.point() + delta * *start);
+            for pair in steps.windows(2) {
+                assert!((pair[1].point() - pair[0].point()).length() <= 1f32 + 1e-4);
+                assert_eq!(pair[1].size(), rect.size());
+            }
+            let last = steps.last().unwrap();
+            assert!((last.point() - (rect.point() + delta)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn bounds_cover_both_ends() {
+        let rect = Rect::new(10f32, 10f32, 4f32, 4f32);
+        let swept = bounds(&rect, vec2(-6f32, 20f32));
+        assert_eq!(swept, Rect::new(4f32, 10f32, 10f32, 24f32));
+    }
+}
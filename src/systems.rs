@@ -0,0 +1,506 @@
+use macroquad::prelude::*;
+
+use crate::{
+    bullet::BulletHurtType,
+    bunker::BunkerManager,
+    constants::*,
+    ecs::{Entity, World},
+    enermy::{EnermyColor, EnermyContext, EnermyDeathMethod, EnermyType},
+    flocking,
+    player::{Player, PlayerCommand, PlayerInput, PlayerMessage, PlayerState},
+    resources::{Resources, SoundQueue},
+    spatial_hash::SpatialHash,
+    wave::{spawn_enermy, LastEnermyDeathReason, SpawnBlueprint, WaveManager}
+};
+
+
+// something the game keeps score of, the collision systems push them in the order they happen
+pub enum GameEvent {
+    PlayerHurt,
+    Graze,
+    ShotDown(Vec2),
+    HazardKill(Vec2),
+    Tink(Vec2), // hit armor, no damage
+    Kill(Vec2, EnermyDeathMethod, EnermyType, EnermyColor), // by the player
+    Miss // a player bullet left the screen
+}
+
+
+// lists the systems fill and empty again every frame, kept between frames so a warm frame doesn't allocate
+pub struct SystemBuffers {
+    culled: Vec<Entity>,
+    swept: Vec<(Entity, Rect)>,
+    nearby: Vec<Entity>,
+    nearby_bullets: Vec<Entity>,
+    centers: Vec<Option<Vec2>>,
+    offsets: Vec<Vec2>
+}
+
+
+impl SystemBuffers {
+    pub fn new() -> Self {
+        SystemBuffers {
+            culled: Vec::new(),
+            swept: Vec::new(),
+            nearby: Vec::new(),
+            nearby_bullets: Vec::new(),
+            centers: Vec::new(),
+            offsets: Vec::new()
+        }
+    }
+}
+
+
+// how far a sprite pointing along forward has to turn to face vel, None while standing still
+pub fn heading_rotation(forward: Vec2, vel: Vec2) -> Option<f32> {
+    if vel.length_squared() <= 0f32 {
+        return None;
+    }
+    Some((forward.x * vel.y - forward.y * vel.x).atan2(forward.dot(vel)))
+}
+
+
+// one frame of the sprite, turned on its side around the same center as the drawing for sideways movers
+pub fn collider_rect(pos: Vec2, frame_size: Vec2, vel: Vec2) -> Rect {
+    let size = if vel.x.abs() > vel.y.abs() { vec2(frame_size.y, frame_size.x) } else { frame_size };
+    let center = pos + frame_size * 0.5f32;
+    Rect::new(center.x - size.x * 0.5f32, center.y - size.y * 0.5f32, size.x, size.y)
+}
+
+
+// integrates motion, turning and delayed acceleration included, and keeps sprites facing the heading
+pub fn movement(world: &mut World, dt: f32) {
+    for entity in world.entities.iter() {
+        let (transform, motion) = match (world.transforms.get_mut(entity), world.bullet_motions.get_mut(entity)) {
+            (Some(transform), Some(motion)) => (transform, motion),
+            _ => continue
+        };
+
+        if motion.turn_speed != 0f32 {
+            let (sin, cos) = (motion.turn_speed * dt).sin_cos();
+            let rotate = |v: Vec2| vec2(v.x * cos - v.y * sin, v.x * sin + v.y * cos);
+            motion.vel = rotate(motion.vel);
+            motion.acc = rotate(motion.acc);
+        }
+        if motion.acc_delay > 0f32 {
+            motion.acc_delay -= dt;
+        } else {
+            motion.vel += motion.acc * dt;
+        }
+        transform.pos += motion.vel * dt;
+
+        if let Some(sprite) = world.sprites.get(entity) {
+            if let Some(rotation) = heading_rotation(sprite.forward, motion.vel) {
+                transform.rotation = rotation;
+            }
+        }
+    }
+}
+
+
+pub fn animation(world: &mut World, dt: f32) {
+    for entity in world.entities.iter() {
+        if let Some(animation) = world.animations.get_mut(entity) {
+            animation.timer += dt;
+            if let Some(loop_time) = animation.loop_time_optional {
+                if animation.timer > loop_time {
+                    animation.timer -= loop_time;
+                }
+            }
+        }
+    }
+}
+
+
+// moves every collider to where its entity ended up, remembering where it started for swept checks
+pub fn colliders(world: &mut World) {
+    for entity in world.entities.iter() {
+        let (collider, transform) = match (world.colliders.get_mut(entity), world.transforms.get(entity)) {
+            (Some(collider), Some(transform)) => (collider, transform),
+            _ => continue
+        };
+        collider.prev_rect = collider.rect;
+        collider.rect = match world.bullet_motions.get(entity) {
+            Some(motion) => collider_rect(transform.pos, collider.frame_size, motion.vel),
+            None => collider.rect_at(transform.pos)
+        };
+    }
+}
+
+
+pub fn draw(world: &World) {
+    for entity in world.entities.iter() {
+        let (transform, sprite) = match (world.transforms.get(entity), world.sprites.get(entity)) {
+            (Some(transform), Some(sprite)) => (transform, sprite),
+            _ => continue
+        };
+        let timer = world.animations.get(entity).map_or(0f32, |animation| animation.timer);
+        let frame = (timer / BULLET_ANIM_TIME_SPAWN * sprite.frame_count) as i32;
+        let frame_width = sprite.texture.width() / sprite.frame_count;
+        draw_texture_ex(
+            sprite.texture,
+            transform.pos.x,
+            transform.pos.y,
+            sprite.tint,
+            DrawTextureParams {
+                rotation: transform.rotation,
+                source: Some(Rect::new(frame_width * frame as f32, 0f32, frame_width, sprite.texture.height())),
+                ..Default::default()
+            }
+        )
+    }
+}
+
+
+// past the screen edge by more than margin
+pub fn is_off_screen(world: &World, entity: Entity, margin: f32) -> bool {
+    let (transform, collider) = match (world.transforms.get(entity), world.colliders.get(entity)) {
+        (Some(transform), Some(collider)) => (transform, collider),
+        _ => return false
+    };
+    let pos = transform.pos;
+    pos.y + collider.rect.h < -margin
+        || pos.y > GAME_SIZE_Y as f32 + margin
+        || pos.x + collider.rect.w < -margin
+        || pos.x > GAME_SIZE_X as f32 + margin
+}
+
+
+// bullets that hit something, left the screen for good or have been around too long, and demons done exploding
+pub fn cull(world: &mut World, buffers: &mut SystemBuffers) {
+    let culled = &mut buffers.culled;
+    culled.clear();
+    for entity in world.entities.iter() {
+        let projectile = match world.projectiles.get(entity) {
+            Some(projectile) => projectile,
+            None => continue
+        };
+        let expired = match world.animations.get(entity) {
+            Some(animation) => animation.timer > BULLET_LIFETIME,
+            None => false
+        };
+        if projectile.is_kill || expired || is_off_screen(world, entity, BULLET_CULL_MARGIN) {
+            culled.push(entity);
+        }
+    }
+    for entity in world.enermy_entities() {
+        if world.enermies.get(entity).unwrap().is_dead() {
+            culled.push(entity);
+        }
+    }
+    for entity in culled.iter() {
+        world.despawn(*entity);
+    }
+}
+
+
+// player bullets against shootable enemy bullets, both break on contact
+// swept bounds sorted along x so every bullet only gets compared with the ones next to it,
+// then the moves are traced against each other so fast shots can't slip through
+pub fn shoot_down(world: &mut World, buffers: &mut SystemBuffers, events: &mut Vec<GameEvent>) {
+    let order = &mut buffers.swept;
+    order.clear();
+    for entity in world.entities.iter() {
+        if let (Some(projectile), Some(collider)) = (world.projectiles.get(entity), world.colliders.get(entity)) {
+            if !projectile.is_kill && (projectile.hurt_type == BulletHurtType::Enermy || projectile.kind.is_shootable()) {
+                order.push((entity, collider.swept_rect()));
+            }
+        }
+    }
+    order.sort_by(|(_, a), (_, b)| a.x.partial_cmp(&b.x).unwrap());
+
+    for (n, (entity, rect)) in order.iter().enumerate() {
+        for (other_entity, other_rect) in order[n + 1..].iter() {
+            if other_rect.x > rect.x + rect.w {
+                break;
+            }
+            let (projectile, other) = (world.projectiles.get(*entity).unwrap(), world.projectiles.get(*other_entity).unwrap());
+            if projectile.is_kill {
+                break;
+            }
+            if other.is_kill || projectile.hurt_type == other.hurt_type || !rect.overlaps(other_rect) {
+                continue;
+            }
+            if !world.colliders.get(*entity).unwrap().sweep_hits_collider(world.colliders.get(*other_entity).unwrap()) {
+                continue;
+            }
+
+            let shootable = if projectile.hurt_type == BulletHurtType::Player { *entity } else { *other_entity };
+            events.push(GameEvent::ShotDown(world.colliders.get(shootable).unwrap().center()));
+            for broken in [*entity, *other_entity].iter() {
+                world.projectiles.get_mut(*broken).unwrap().is_kill = true;
+            }
+        }
+    }
+}
+
+
+// one update of the player, taken out of the world along with where it stands so it can shoot into it
+pub fn player(
+    world: &mut World,
+    entity: Entity,
+    dt: f32,
+    input: &PlayerInput,
+    resources: &Resources,
+    sound_queue: &mut SoundQueue
+) -> Option<PlayerMessage> {
+    let mut player = world.players.take(entity)?;
+    let mut transform = world.transforms.take(entity)?;
+    let message_optional = player.update(&mut transform, dt, input, world, resources, sound_queue);
+    world.transforms.put(entity, transform);
+    world.players.put(entity, player);
+    message_optional
+}
+
+
+// flocking first, then every demon's state machine in spawn order, aimed at the player
+pub fn enermies(
+    world: &mut World,
+    buffers: &mut SystemBuffers,
+    player: Entity,
+    dt: f32,
+    resources: &Resources,
+    wave_manager: &mut WaveManager,
+    sound_queue: &mut SoundQueue
+) {
+    flocking::separate(world, &mut buffers.centers, &mut buffers.offsets, dt);
+    let (player_pos, player_vel) = match (world.players.get(player), world.transforms.get(player), world.colliders.get(player)) {
+        // the player moved after the colliders system ran last frame, aim at where it stands now
+        (Some(player), Some(transform), Some(collider)) => {
+            let rect = collider.rect_at(transform.pos);
+            (rect.point() + rect.size() * 0.5f32, player.vel)
+        }
+        _ => return
+    };
+    let mut context = EnermyContext { dt, world, resources, player_pos, player_vel, wave_manager, sound_queue };
+    for index in 0..context.world.enermy_count() {
+        let entity = context.world.enermy_at(index);
+        let mut enemy = context.world.enermies.take(entity).unwrap();
+        let mut transform = context.world.transforms.take(entity).unwrap();
+        let collider = context.world.colliders.take(entity).unwrap();
+        enemy.update(&mut transform, &collider, &mut context);
+        context.world.colliders.put(entity, collider);
+        context.world.transforms.put(entity, transform);
+        context.world.enermies.put(entity, enemy);
+    }
+}
+
+
+// bullets from either side chip the bunkers, homing and diving demons crash into them
+pub fn bunkers(world: &mut World, bunkers: &mut BunkerManager, wave_manager: &mut WaveManager) {
+    for entity in world.entities.iter() {
+        let (projectile, collider) = match (world.projectiles.get_mut(entity), world.colliders.get(entity)) {
+            (Some(projectile), Some(collider)) if !projectile.is_kill => (projectile, collider),
+            _ => continue
+        };
+        if collider.swept_rects().any(|rect| bunkers.hit(&rect, BUNKER_CHIP_RADIUS)) {
+            projectile.is_kill = true;
+        }
+    }
+
+    for index in 0..world.enermy_count() {
+        let entity = world.enermy_at(index);
+        let (enemy, collider) = (world.enermies.get_mut(entity).unwrap(), world.colliders.get(entity).unwrap());
+        if enemy.hurts_on_contact() && collider.swept_rects().any(|rect| bunkers.hit(&rect, BUNKER_CHIP_RADIUS_CONTACT)) {
+            enemy.state_shared.health = 0;
+            wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Environment;
+        }
+    }
+}
+
+
+// takes a life unless the player is still blinking from the last one, true if it did
+fn hurt_player(player: &mut Player, events: &mut Vec<GameEvent>) -> bool {
+    if player.state != PlayerState::Normal {
+        return false;
+    }
+    player.process_optional_command(Some(PlayerCommand::ChangeState(PlayerState::Invincible(PLAYER_TIME_INVISBLE))));
+    events.push(GameEvent::PlayerHurt);
+    true
+}
+
+
+// hazards hurt the player and the demons alike, bosses shrug them off
+pub fn hazards(world: &mut World, player: Entity, wave_manager: &mut WaveManager, events: &mut Vec<GameEvent>) {
+    let hazards = &mut wave_manager.hazards;
+    if let (Some(player), Some(collider)) = (world.players.get_mut(player), world.colliders.get(player)) {
+        if player.state == PlayerState::Normal && hazards.hit(&collider.rect) {
+            hurt_player(player, events);
+        }
+    }
+
+    for index in 0..world.enermy_count() {
+        let entity = world.enermy_at(index);
+        let enemy = world.enermies.get_mut(entity).unwrap();
+        if !enemy.is_alive() || matches!(enemy.state_shared.enermy_type, EnermyType::Boss) {
+            continue;
+        }
+        if hazards.hit(&world.colliders.get(entity).unwrap().rect) {
+            enemy.state_shared.health = 0;
+            wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Environment;
+            events.push(GameEvent::HazardKill(world.transforms.get(entity).unwrap().pos));
+        }
+    }
+}
+
+
+// everything that can still hit something, by the ground it covered this update
+pub fn broadphase(world: &World, bullet_hash: &mut SpatialHash<Entity>, enermy_hash: &mut SpatialHash<Entity>) {
+    bullet_hash.clear();
+    for entity in world.entities.iter() {
+        if let (Some(projectile), Some(collider)) = (world.projectiles.get(entity), world.colliders.get(entity)) {
+            if !projectile.is_kill {
+                bullet_hash.insert(entity, &collider.swept_rect());
+            }
+        }
+    }
+    enermy_hash.clear();
+    for entity in world.enermy_entities() {
+        if world.enermies.get(entity).unwrap().is_alive() {
+            enermy_hash.insert(entity, &world.colliders.get(entity).unwrap().swept_rect());
+        }
+    }
+}
+
+
+// enemy bullets and ramming demons hurting the player, near misses fill the graze meter
+pub fn player_damage(
+    world: &mut World,
+    player: Entity,
+    bullet_hash: &SpatialHash<Entity>,
+    enermy_hash: &SpatialHash<Entity>,
+    buffers: &mut SystemBuffers,
+    events: &mut Vec<GameEvent>
+) {
+    let (player, player_rect) = match (world.players.get_mut(player), world.colliders.get(player)) {
+        (Some(player), Some(collider)) => (player, collider.rect),
+        _ => return
+    };
+    let (nearby_bullets, nearby) = (&mut buffers.nearby_bullets, &mut buffers.nearby);
+
+    bullet_hash.query(&player_rect, nearby_bullets);
+    for entity in nearby_bullets.iter() {
+        let projectile = world.projectiles.get_mut(*entity).unwrap();
+        let collider = world.colliders.get(*entity).unwrap();
+        if projectile.hurt_type == BulletHurtType::Player && collider.sweep_hits(&player_rect, |rect| rect.overlaps(&player_rect)) {
+            if !hurt_player(player, events) {
+                continue;
+            }
+            projectile.is_kill = true;
+            break;
+        }
+    }
+
+    if player.state == PlayerState::Normal {
+        let player_center = player_rect.point() + player_rect.size() * 0.5f32;
+        let graze_rect = Rect::new(
+            player_center.x - PLAYER_GRAZE_RADIUS,
+            player_center.y - PLAYER_GRAZE_RADIUS,
+            PLAYER_GRAZE_RADIUS * 2f32,
+            PLAYER_GRAZE_RADIUS * 2f32
+        );
+        bullet_hash.query(&graze_rect, nearby_bullets);
+        for entity in nearby_bullets.iter() {
+            let projectile = world.projectiles.get_mut(*entity).unwrap();
+            if projectile.hurt_type != BulletHurtType::Player || projectile.is_kill || projectile.grazed {
+                continue;
+            }
+            if world.colliders.get(*entity).unwrap().center().distance(player_center) < PLAYER_GRAZE_RADIUS {
+                projectile.grazed = true;
+                player.add_graze();
+                events.push(GameEvent::Graze);
+            }
+        }
+    }
+
+    enermy_hash.query(&player_rect, nearby);
+    for entity in nearby.iter() {
+        let enemy = world.enermies.get_mut(*entity).unwrap();
+        let (collider, animation) = (world.colliders.get(*entity).unwrap(), world.animations.get(*entity).unwrap());
+        if enemy.hurts_on_contact() && enemy.sweep_overlaps(collider, animation, &player_rect) && hurt_player(player, events) {
+            enemy.state_shared.health = 0;
+        }
+    }
+}
+
+
+// player bullets hurting demons, each bullet breaks on the first one it reaches
+pub fn enermy_damage(
+    world: &mut World,
+    enermy_hash: &SpatialHash<Entity>,
+    buffers: &mut SystemBuffers,
+    wave_manager: &mut WaveManager,
+    events: &mut Vec<GameEvent>
+) {
+    let nearby = &mut buffers.nearby;
+    for entity in world.entities.iter() {
+        let (projectile, collider, motion) = match (
+            world.projectiles.get_mut(entity),
+            world.colliders.get(entity),
+            world.bullet_motions.get(entity)
+        ) {
+            (Some(projectile), Some(collider), Some(motion)) if projectile.hurt_type == BulletHurtType::Enermy && !projectile.is_kill => {
+                (projectile, collider, motion)
+            }
+            _ => continue
+        };
+        enermy_hash.query(&collider.swept_rect(), nearby);
+        for enermy_entity in nearby.iter() {
+            let enemy = world.enermies.get_mut(*enermy_entity).unwrap();
+            let (enermy_collider, animation) = (world.colliders.get(*enermy_entity).unwrap(), world.animations.get(*enermy_entity).unwrap());
+            let pos = world.transforms.get(*enermy_entity).unwrap().pos;
+            if projectile.is_kill || !collider.sweep_hits(&enermy_collider.rect, |rect| enemy.overlaps(enermy_collider, animation, rect)) {
+                continue;
+            }
+            projectile.is_kill = true;
+            if !enemy.take_hit(motion.vel, projectile.kind) {
+                events.push(GameEvent::Tink(pos));
+                continue;
+            }
+            wave_manager.last_enermydeath_reason = LastEnermyDeathReason::Player;
+            if enemy.state_shared.health <= 0 {
+                enemy.state_shared.killed_by_player = true;
+                let shared = &enemy.state_shared;
+                events.push(GameEvent::Kill(pos, shared.death_method, shared.enermy_type, shared.enermy_color));
+            }
+        }
+    }
+}
+
+
+// player bullets that leave the screen without hitting anything
+pub fn misses(world: &mut World, events: &mut Vec<GameEvent>) {
+    for entity in world.entities.iter() {
+        let off_screen = is_off_screen(world, entity, 0f32);
+        if let Some(projectile) = world.projectiles.get_mut(entity) {
+            if projectile.hurt_type == BulletHurtType::Enermy && !projectile.is_kill && off_screen {
+                events.push(GameEvent::Miss);
+                projectile.is_kill = true;
+            }
+        }
+    }
+}
+
+
+// the minis a demon breaks into, fanned out upwards so they don't start stacked
+pub fn spawn_brood(
+    world: &mut World,
+    resources: &Resources,
+    pos: Vec2,
+    amount: i32,
+    enermy_color: EnermyColor,
+    wave_count: i32,
+    brood_id: u32
+) {
+    let spawn_width = 20f32;
+    let step = 1. / (amount as f32);
+    for i in 0..amount {
+        let spawn_pos = pos + vec2(step * spawn_width * i as f32, 0f32);
+        let entity = spawn_enermy(world, resources, SpawnBlueprint::Mini(spawn_pos), enermy_color, wave_count);
+        let child = world.enermies.get_mut(entity).unwrap();
+        child.state_shared.brood_id_optional = Some(brood_id);
+        let fraction = if amount > 1 { i as f32 / (amount - 1) as f32 } else { 0.5f32 };
+        let angle = (fraction - 0.5f32) * ENERMY_CHILDREN_FAN_OUT_ANGLE.to_radians();
+        child.push(vec2(angle.sin(), -angle.cos()) * ENERMY_CHILDREN_FAN_OUT_SPEED);
+    }
+}
@@ -1,25 +1,38 @@
 
 use macroquad::prelude::*; 
-use quad_snd::mixer::{ SoundMixer, Volume}; 
+use quad_snd::mixer::Volume; 
 
 use crate::{
     constants::*, 
     enermy::{ EnermyColor, EnermyType, Enermy, EnermyDeathMethod},
-    resources::{ Resources, SoundIdentifier }, 
+    ecs::{ Animation, Entity, Transform, World }, 
+    resources::{ Resources, SoundIdentifier, SoundQueue }, 
+    formation::Formation, 
+    hazard::HazardManager, 
     variant_eq
 }; 
 
 
 pub struct WaveManagerStateSpawning {
     enermies_left: i32,
-    spawn_timer: f32
+    spawn_timer: f32, 
+    formation_wave: bool
+}
+
+
+
+pub struct WaveManagerStateBossWarning {
+    timer: f32, 
+    sound_timer: f32
 }
 
 
 
 pub enum WaveManagerState {
     Spawning(WaveManagerStateSpawning),
-    Battle
+    Battle, 
+    BossWarning(WaveManagerStateBossWarning), 
+    Boss // normal spawning is on hold until the boss and whatever is left are dead
 }
 
 
@@ -46,7 +59,12 @@ pub enum LastEnermyDeathReason {
 pub struct WaveManager {
     pub state: WaveManagerState,
     pub last_enermydeath_reason: LastEnermyDeathReason, 
-    internal_timer: f32
+    internal_timer: f32, 
+    pub wave_count: i32, 
+    incoming_spawns: Vec<SpawnBlueprint>, // spawns sent over by the opponent in versus mode
+    incoming_spawn_timer: f32, 
+    pub formation: Formation, 
+    pub hazards: HazardManager
 }
 
 
@@ -59,9 +77,15 @@ impl WaveManager {
             state: WaveManagerState::Spawning(WaveManagerStateSpawning {
                 spawn_timer: 0f32, 
                 enermies_left,
+                formation_wave: false
             }),
             last_enermydeath_reason: LastEnermyDeathReason::Environment,
-            internal_timer: 0f32
+            internal_timer: 0f32, 
+            wave_count: 0, 
+            incoming_spawns: Vec::<SpawnBlueprint>::new(), 
+            incoming_spawn_timer: 0f32, 
+            formation: Formation::new(), 
+            hazards: HazardManager::new()
         }
     }
 
@@ -80,30 +104,81 @@ impl WaveManager {
         let enermies_left = ENERMY_SPAWN_STARTING_COUNT; 
         self.state = WaveManagerState::Spawning(WaveManagerStateSpawning {
             spawn_timer: 0f32, 
-            enermies_left
+            enermies_left, 
+            formation_wave: false
         }); 
 
         self.last_enermydeath_reason = LastEnermyDeathReason::Environment; 
         self.internal_timer = 0f32; 
+        self.wave_count = 0; 
+        self.incoming_spawns.clear(); 
+        self.incoming_spawn_timer = 0f32; 
+        self.formation.reset(); 
+        self.hazards.reset(); 
+    }
+
+    pub fn queue_spawn(&mut self, spawn_blueprint: SpawnBlueprint) {
+        self.incoming_spawns.push(spawn_blueprint); 
+    }
+
+    fn update_incoming_spawns(
+        &mut self, 
+        dt: f32, 
+        world: &mut World, 
+        resources: &Resources, 
+        sound_queue: &mut SoundQueue
+    ) {
+        if self.incoming_spawns.is_empty() {
+            self.incoming_spawn_timer = 0f32; 
+            return; 
+        }
+
+        // trickle them in so a big attack doesn't land all on the same frame
+        self.incoming_spawn_timer += dt; 
+        if self.incoming_spawn_timer > VERSUS_ATTACK_SPAWN_TIME {
+            self.incoming_spawn_timer -= VERSUS_ATTACK_SPAWN_TIME; 
+            let spawn_blueprint = self.incoming_spawns.remove(0); 
+            spawn_enermy(world, resources, spawn_blueprint, EnermyColor::random(), self.wave_count); 
+            resources.play_sound(SoundIdentifier::Spawn, sound_queue, Volume(0.4f32)); 
+        }
     }
 
     pub fn update(
         &mut self, 
         dt: f32, 
-        enermies: &mut Vec<Enermy>,
+        world: &mut World,
         resources: &Resources, 
-        sound_mixer: &mut SoundMixer
+        sound_queue: &mut SoundQueue
      ) -> Option<WaveManagerMessage> {
         self.internal_timer += dt;
+        self.formation.update(dt, world); 
+        // no hazards during boss fights
+        let hazards_can_start = self.wave_count >= HAZARD_FIRST_WAVE
+            && matches!(self.state, WaveManagerState::Spawning(_) | WaveManagerState::Battle); 
+        self.hazards.update(dt, hazards_can_start, resources, sound_queue); 
+        self.update_incoming_spawns(dt, world, resources, sound_queue); 
         let state_command_optional = match &mut self.state {
             WaveManagerState::Spawning(game_state_spawing) => Self::update_state_spawning(
                 game_state_spawing, 
                 dt, 
-                enermies, 
+                world, 
+                &mut self.formation, 
+                &self.wave_count, 
                 resources, 
-                sound_mixer
+                sound_queue
             ), 
-            WaveManagerState::Battle => Self::update_state_battle(enermies, &self.internal_timer)
+            WaveManagerState::Battle => {
+                Self::update_state_battle(world, &self.internal_timer, &mut self.wave_count)
+            }
+            WaveManagerState::BossWarning(state_data) => Self::update_state_boss_warning(
+                state_data, 
+                dt, 
+                world, 
+                &self.wave_count, 
+                resources, 
+                sound_queue
+            ), 
+            WaveManagerState::Boss => Self::update_state_boss(world, &self.internal_timer, &self.wave_count)
         };
 
 
@@ -115,7 +190,13 @@ impl WaveManager {
                     let cleared_screen = variant_eq(&self.state,
                         &WaveManagerState::Spawning(WaveManagerStateSpawning {
                             spawn_timer: 0f32, 
-                            enermies_left: 0
+                            enermies_left: 0, 
+                            formation_wave: false
+                        }),
+                    ) || variant_eq(&self.state, 
+                        &WaveManagerState::BossWarning(WaveManagerStateBossWarning {
+                            timer: 0f32, 
+                            sound_timer: 0f32
                         }),
                     ); 
 
@@ -131,22 +212,36 @@ impl WaveManager {
     pub fn update_state_spawning (
         game_state_spawning: &mut WaveManagerStateSpawning,
         dt: f32,
-        enermies: &mut Vec<Enermy>, 
+        world: &mut World, 
+        formation: &mut Formation, 
+        wave_count: &i32, 
         resources: &Resources, 
-        sound_mixer: &mut SoundMixer 
+        sound_queue: &mut SoundQueue 
     ) -> Option<WaveManagerCommand> {
         game_state_spawning.spawn_timer += dt; 
         if game_state_spawning.spawn_timer > ENERMY_SPAWN_TIME {
             game_state_spawning.enermies_left -= 1; 
             game_state_spawning.spawn_timer -= ENERMY_SPAWN_TIME; 
 
-            spawn_enermy(
-                enermies,
+            // formation waves fall back to normal spawns once the grid is full
+            let formation_slot_optional = if game_state_spawning.formation_wave {
+                formation.claim_slot()
+            } else {
+                None
+            }; 
+
+            let entity = spawn_enermy(
+                world,
                 resources, 
-                SpawnBlueprint::Normal, 
-                EnermyColor::random()
+                pick_spawn_blueprint(*wave_count), 
+                EnermyColor::random(), 
+                *wave_count
             ); 
-            resources.play_sound(SoundIdentifier::Spawn, sound_mixer, Volume(0.4f32)); 
+            if let Some(slot) = formation_slot_optional {
+                let transform = world.transforms.get_mut(entity).unwrap(); 
+                world.enermies.get_mut(entity).unwrap().enter_formation(transform, slot, formation); 
+            }
+            resources.play_sound(SoundIdentifier::Spawn, sound_queue, Volume(0.4f32)); 
         }
 
         if game_state_spawning.enermies_left <= 0 {
@@ -156,17 +251,68 @@ impl WaveManager {
     }
 
     fn update_state_battle(
-        enermies: &mut Vec<Enermy>, 
+        world: &World, 
         internal_timer: &f32,
+        wave_count: &mut i32,
     ) -> Option<WaveManagerCommand> {
-        if enermies.is_empty() {
-            let enermies_left = Self::get_enermy_spawn_count(internal_timer); 
-            return Some(WaveManagerCommand::ChangeState(WaveManagerState::Spawning(
-                WaveManagerStateSpawning {
-                    enermies_left, 
-                    spawn_timer: 0f32
-                }
-            )))
+        // exploding enemies don't hold up the next wave
+        if world.enermies().all(|e| !e.is_alive()) {
+            *wave_count += 1; 
+            if *wave_count % BOSS_WAVE_INTERVAL == 0 {
+                return Some(WaveManagerCommand::ChangeState(WaveManagerState::BossWarning(
+                    WaveManagerStateBossWarning {
+                        timer: 0f32, 
+                        sound_timer: 0f32
+                    }
+                )))
+            }
+
+            return Some(Self::next_wave(internal_timer, wave_count))
+        }
+        None
+    }
+
+    fn next_wave(internal_timer: &f32, wave_count: &i32) -> WaveManagerCommand {
+        let enermies_left = Self::get_enermy_spawn_count(internal_timer); 
+        WaveManagerCommand::ChangeState(WaveManagerState::Spawning(
+            WaveManagerStateSpawning {
+                enermies_left, 
+                spawn_timer: 0f32, 
+                formation_wave: wave_count % FORMATION_WAVE_INTERVAL == FORMATION_WAVE_INTERVAL - 1
+            }
+        ))
+    }
+
+    fn update_state_boss_warning(
+        state_data: &mut WaveManagerStateBossWarning, 
+        dt: f32, 
+        world: &mut World, 
+        wave_count: &i32, 
+        resources: &Resources, 
+        sound_queue: &mut SoundQueue
+    ) -> Option<WaveManagerCommand> {
+        state_data.timer += dt; 
+        state_data.sound_timer -= dt; 
+        if state_data.sound_timer <= 0f32 {
+            state_data.sound_timer += BOSS_WARNING_SOUND_TIME; 
+            resources.play_sound(SoundIdentifier::Warning, sound_queue, Volume(1.0f32)); 
+        }
+
+        if state_data.timer >= BOSS_WARNING_TIME {
+            spawn_enermy(world, resources, SpawnBlueprint::Boss, EnermyColor::RED, *wave_count); 
+            resources.play_sound(SoundIdentifier::Spawn, sound_queue, Volume(0.8f32)); 
+            return Some(WaveManagerCommand::ChangeState(WaveManagerState::Boss))
+        }
+        None
+    }
+
+    fn update_state_boss(
+        world: &World, 
+        internal_timer: &f32,
+        wave_count: &i32,
+    ) -> Option<WaveManagerCommand> {
+        if world.enermies().all(|e| !e.is_alive()) {
+            return Some(Self::next_wave(internal_timer, wave_count))
         }
         None
     }
@@ -175,51 +321,110 @@ impl WaveManager {
 
 pub enum SpawnBlueprint {
     Normal, 
-    Mini(Vec2)
+    Mini(Vec2), 
+    Boss, 
+    Archetype(EnermyType) // kamikaze, shielded or sniper, spawned like a normal demon
+}
+
+
+// rolls normal demons or one of the archetypes that are unlocked by this wave
+fn pick_spawn_blueprint(wave_count: i32) -> SpawnBlueprint {
+    let unlocked = ENERMY_ARCHETYPE_WEIGHTS.iter().filter(|(_, _, first_wave)| wave_count >= *first_wave); 
+    let weight_total: f32 = unlocked.clone().map(|(_, weight, _)| weight).sum(); 
+
+    let mut roll = rand::gen_range(0f32, weight_total); 
+    for (enermy_type, weight, _) in unlocked {
+        if roll < *weight {
+            return match enermy_type {
+                EnermyType::NORMAL => SpawnBlueprint::Normal, 
+                _ => SpawnBlueprint::Archetype(*enermy_type)
+            }
+        }
+        roll -= weight; 
+    }
+    SpawnBlueprint::Normal
 }
 
 
 pub fn spawn_enermy(
-    enermies: &mut Vec<Enermy>, 
+    world: &mut World, 
     resources: &Resources, 
     spawn_blueprint: SpawnBlueprint, 
-    enermy_color: EnermyColor
-) {
-    let health = 1;
-    let enermy = match spawn_blueprint {
-        SpawnBlueprint::Normal => {
+    enermy_color: EnermyColor, 
+    wave_count: i32
+) -> Entity {
+    let health = enermy_health(&spawn_blueprint, wave_count);
+    let enermy_type = match spawn_blueprint {
+        SpawnBlueprint::Normal => EnermyType::NORMAL, 
+        SpawnBlueprint::Mini(_) => EnermyType::MINI, 
+        SpawnBlueprint::Boss => EnermyType::Boss, 
+        SpawnBlueprint::Archetype(enermy_type) => enermy_type
+    }; 
+    let profile = resources.enermy_profiles.get(enermy_type.profile_name(enermy_color)); 
+    let (pos, enermy) = match spawn_blueprint {
+        SpawnBlueprint::Normal | SpawnBlueprint::Archetype(_) => {
             let spawn_offset = vec2(
                 rand::gen_range(-100f32, 100f32), 
                 rand::gen_range(-60f32, 10f32)
             ); 
 
             let spawn_pos = vec2(GAME_CENTER_X, GAME_CENTER_Y) + spawn_offset; 
-            let death_method = if rand::gen_range(0f32, 1f32) > 0.5f32 {
-                let spawn_amount = rand::gen_range(1, 2 + 1); 
-                EnermyDeathMethod::SpawnChildren(spawn_amount) 
-            }else {
-                EnermyDeathMethod::None
-            };
-
-            Enermy::new(
-                spawn_pos, 
-                resources.rand_enemy_normal(enermy_color), 
+            let death_method = profile.roll_death_method();
+
+            let path_optional = profile.pick_path()
+                .and_then(|path| resources.enermy_paths.follower(&path, spawn_pos)); 
+            let sheet = match enermy_type {
+                EnermyType::NORMAL => resources.rand_enemy_normal(enermy_color), 
+                _ => resources.rand_enermy_archetype(enermy_type)
+            }; 
+            let mut enermy = Enermy::new(
+                sheet, 
                 health, 
                 death_method, 
-                EnermyType::NORMAL,
-                enermy_color
-            )
+                enermy_type,
+                enermy_color,
+                profile
+            ); 
+            enermy.state_shared.path_optional = path_optional; 
+            (spawn_pos, enermy)
         }
 
-        SpawnBlueprint::Mini(pos) => Enermy::new(
-            pos, 
+        SpawnBlueprint::Mini(pos) => (pos, Enermy::new(
             resources.rand_enermy_mini(enermy_color),
             health, 
             EnermyDeathMethod::None, 
             EnermyType::MINI, 
-            enermy_color
-        ),
+            enermy_color, 
+            profile
+        )),
+
+        SpawnBlueprint::Boss => (vec2(GAME_CENTER_X, BOSS_HOVER_Y), Enermy::new(
+            resources.rand_enermy_boss(), 
+            health, 
+            EnermyDeathMethod::None, 
+            EnermyType::Boss, 
+            enermy_color, 
+            profile
+        )),
     };
 
-    enermies.push(enermy);  
-}
\ No newline at end of file
+    let entity = world.spawn(); 
+    world.insert_transform(entity, Transform { pos, rotation: 0f32 }); 
+    world.insert_animation(entity, Animation { timer: 0f32, loop_time_optional: Some(ENERMY_ANIM_TIME_FLAP * 4f32) }); 
+    world.insert_collider(entity, enermy.collider_at(pos)); 
+    world.insert_enermy(entity, enermy); 
+    entity
+}
+
+
+// demons get tougher as the waves go on, minis at half the rate
+fn enermy_health(spawn_blueprint: &SpawnBlueprint, wave_count: i32) -> i32 {
+    match spawn_blueprint {
+        SpawnBlueprint::Normal | SpawnBlueprint::Archetype(_) => (1 + wave_count / ENERMY_HEALTH_WAVES_PER_HIT).min(ENERMY_HEALTH_MAX), 
+        SpawnBlueprint::Mini(_) => (1 + wave_count / (ENERMY_HEALTH_WAVES_PER_HIT * 2)).min(ENERMY_HEALTH_MAX), 
+        SpawnBlueprint::Boss => {
+            let earlier_bosses = (wave_count / BOSS_WAVE_INTERVAL - 1).max(0); 
+            BOSS_HEALTH + earlier_bosses * BOSS_HEALTH_PER_APPEARANCE
+        }
+    }
+}